@@ -1,6 +1,20 @@
 use proc_macro::{TokenStream, };
 use quote::{quote, ToTokens, format_ident};
+use syn::{AttributeArgs, Lit, Meta, NestedMeta};
 
+///
+/// ## #[permission(...)]
+///
+/// Guards a JS function with a permission, throwing a `SecurityException` if the extension doesn't have it:
+///
+/// ```ignore
+/// #[permission(avdan.net.fetch, resource = args.get(0).to_rust_string_lossy(scope), description = "Make HTTP requests.")]
+/// pub fn fetch(...) { ... }
+/// ```
+///
+/// The permission is also registered (with its description) as an `Avdan::security::PermissionInfo`,
+/// which manifests are validated against.
+///
 #[proc_macro_attribute]
 pub fn permission(attr: TokenStream, content : TokenStream) -> TokenStream {
     let mut item: syn::Item = syn::parse(content).unwrap();
@@ -28,17 +42,70 @@ pub fn permission(attr: TokenStream, content : TokenStream) -> TokenStream {
         None    => panic!("fn must have &mut v8::HandleScope!")
     };
 
-    let perm = attr.to_string();
+    // #[permission(avdan.net.fetch, resource = <expr>)]
+    let attr : proc_macro2::TokenStream = attr.into();
+    let mut tokens = attr.into_iter();
+
+    let perm : proc_macro2::TokenStream = tokens.by_ref()
+        .take_while(|t| !matches!(t, proc_macro2::TokenTree::Punct(p) if p.as_char() == ','))
+        .collect();
+    let perm = perm.to_string().replace(" ", "");
+
+    // The rest: `key = value` pairs, split on top-level commas.
+    let rest : Vec<proc_macro2::TokenTree> = tokens.collect();
+    let mut resource    : Option<proc_macro2::TokenStream> = None;
+    let mut description : String = String::new();
+
+    for pair in rest.split(|t| matches!(t, proc_macro2::TokenTree::Punct(p) if p.as_char() == ',')) {
+        match pair {
+            [] => {},
+            [proc_macro2::TokenTree::Ident(i), proc_macro2::TokenTree::Punct(p), expr @ ..] if i == "resource" && p.as_char() == '=' => {
+                resource = Some(expr.iter().cloned().collect());
+            },
+            [proc_macro2::TokenTree::Ident(i), proc_macro2::TokenTree::Punct(p), proc_macro2::TokenTree::Literal(l)] if i == "description" && p.as_char() == '=' => {
+                description = match syn::parse_str::<syn::LitStr>(&l.to_string()) {
+                    Ok(s)  => s.value(),
+                    Err(_) => panic!("#[permission] description must be a string!")
+                };
+            },
+            _ => panic!("expected #[permission(name, resource = <expr>, description = \"...\")] !")
+        }
+    }
 
     // Add these lines to the start of the function
     let scope_var = format_ident!("{}", scope);
 
-    let q = quote! {
-        match Avdan::security::Constraints::from_scope(#scope_var)
-              .throw_permission_exception(#scope_var, #perm) {
-          true  => {},
-          false => return
-        };
+    // Registers the permission, so manifests can be checked against it.
+    let register = quote! {
+        inventory::submit! {
+            crate::Avdan::security::PermissionInfo {
+                name        : #perm,
+                description : #description,
+            }
+        }
+    };
+
+    let q = match resource {
+        None => quote! {
+            match Avdan::security::Constraints::from_scope(#scope_var)
+                  .throw_permission_exception(#scope_var, #perm) {
+              true  => {},
+              false => return
+            };
+        },
+
+        // Evaluated first, as it may need the scope too.
+        Some(resource) => quote! {
+            match {
+                let resource : String = { #resource };
+
+                Avdan::security::Constraints::from_scope(#scope_var)
+                    .throw_resource_exception(#scope_var, #perm, &resource)
+            } {
+              true  => {},
+              false => return
+            };
+        },
     };
 
     let to_add = syn::parse (
@@ -56,7 +123,76 @@ pub fn permission(attr: TokenStream, content : TokenStream) -> TokenStream {
         
         Ok(body) => {
             fn_item.block.stmts.insert(0, body);
+            fn_item.block.stmts.insert(0, syn::parse(register.into()).unwrap());
             fn_item.to_token_stream().into()
         }
     }
 }
+
+///
+/// ## #[signature(...)]
+///
+/// Describes a JS function for the TypeScript declarations (`proj types`):
+///
+/// ```ignore
+/// #[signature(params = "cmd: string, ...args: string[]", returns = "void")]
+/// #[permission(avdan.shell.exec)]
+/// pub fn exec(...) { ... }
+/// ```
+///
+/// Adds an associated `const exec__SIGNATURE : crate::core::Signature` next to the function.
+/// The JS name defaults to the function's name (override it with `name = "..."`),
+/// and the permission is taken from a `#[permission]` **below** this attribute.
+///
+#[proc_macro_attribute]
+pub fn signature(attr: TokenStream, content : TokenStream) -> TokenStream {
+    let args = syn::parse_macro_input!(attr as AttributeArgs);
+    let fn_item: syn::ImplItemMethod = syn::parse(content).expect("expected fn inside of an impl !");
+
+    let mut name    = fn_item.sig.ident.to_string();
+    let mut params  = String::new();
+    let mut returns = "void".to_string();
+
+    for arg in args {
+        match arg {
+            NestedMeta::Meta(Meta::NameValue(nv)) => {
+                let value = match nv.lit {
+                    Lit::Str(s) => s.value(),
+                    _           => panic!("#[signature] values must be strings!")
+                };
+
+                match nv.path.get_ident().map(|i| i.to_string()).as_deref() {
+                    Some("name")    => name    = value,
+                    Some("params")  => params  = value,
+                    Some("returns") => returns = value,
+                    _               => panic!("#[signature] takes `name`, `params` and `returns`!")
+                }
+            },
+            _ => panic!("#[signature] takes `name = \"...\"`, `params = \"...\"` and `returns = \"...\"`!")
+        }
+    }
+
+    let permission = fn_item.attrs.iter()
+        .find(|a| a.path.segments.last().map(|s| s.ident == "permission").unwrap_or(false))
+        .map(|a| a.parse_args::<proc_macro2::TokenStream>().unwrap().to_string())
+        .map(|p| p.split(",").next().unwrap().replace(" ", ""));
+
+    let permission = match permission {
+        Some(p) => quote! { Some(#p) },
+        None    => quote! { None },
+    };
+
+    let const_name = format_ident!("{}__SIGNATURE", fn_item.sig.ident);
+
+    quote! {
+        #[allow(non_upper_case_globals)]
+        pub const #const_name : crate::core::Signature = crate::core::Signature {
+            name       : #name,
+            params     : #params,
+            returns    : #returns,
+            permission : #permission,
+        };
+
+        #fn_item
+    }.into()
+}
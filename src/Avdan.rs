@@ -34,14 +34,35 @@ mod runtime;
 
 pub struct Permission {
     contents : String,
+    /// The resources a scoped permission is limited to, e.g. `~/Documents/**` in
+    /// `avdan.file.read:~/Documents/**`.
+    pattern  : Option<String>,
 }
 
 impl Permission {
     pub fn new(contents : &str) -> Permission {
+        let (contents, pattern) = match contents.split_once(":") {
+            Some((name, pattern)) => (name, Some(pattern.to_string())),
+            None                  => (contents, None),
+        };
+
         Permission {
-            contents : String::from(contents)
+            contents : String::from(contents),
+            pattern,
         }
     }
+
+    pub fn name(&self) -> &str {
+        &self.contents
+    }
+
+    pub fn pattern(&self) -> Option<&str> {
+        self.pattern.as_deref()
+    }
+
+    pub fn is_scoped(&self) -> bool {
+        self.pattern.is_some()
+    }
     
     pub fn has(&self, permission: &str) -> bool {
         let to_check_arr   : Vec<&str> = self.contents.split(".").collect();
@@ -61,6 +82,19 @@ impl Permission {
 
         return true;
     }
+
+    /// Whether this permission allows `permission` on a concrete resource
+    /// (a path, an URL...), checking its pattern if it's scoped.
+    pub fn has_resource(&self, permission: &str, resource: &str) -> bool {
+        if !self.has(permission) {
+            return false;
+        }
+
+        match &self.pattern {
+            None          => true,
+            Some(pattern) => security::resource_matches(pattern, resource),
+        }
+    }
 }
 
 pub use runtime::{Runtime, PromIndex};
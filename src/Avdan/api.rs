@@ -6,17 +6,43 @@ pub mod debug;
 mod shell;
 mod pipe;
 mod net;
+mod secrets;
+mod security;
+mod typings;
 
-use crate::core::JSApi;
+use crate::{core::JSApi, Avdan::security::PermissionInfo};
+
+// `clipboard` isn't built for now, but manifests (like `test/clipboard`) still ask for its permissions
+// -- so they're registered here, as its `#[permission]`s would.
+inventory::submit! {
+    PermissionInfo {
+        name        : "avdan.clipboard.read",
+        description : "Read the clipboard.",
+    }
+}
+
+inventory::submit! {
+    PermissionInfo {
+        name        : "avdan.clipboard.write",
+        description : "Write to, or clear, the clipboard.",
+    }
+}
+
+inventory::submit! {
+    PermissionInfo {
+        name        : "avdan.clipboard.type",
+        description : "Type text into the focused window.",
+    }
+}
 
 // use clipboard::AvClipboard;
 use shell::AvShell;
-use self::{debug::AvDebug, net::AvNet};
+use self::{debug::AvDebug, net::AvNet, secrets::AvSecrets, security::AvSecurity};
 
 pub struct AvdanAPI {}
 
 impl AvdanAPI {
-    fn assign_mod_to_obj<'a>(scope: &mut HandleScope<'a>, obj: Local<Object>, name: &str, module: &impl JSApi) -> () {
+    fn assign_mod_to_obj<'a>(scope: &mut HandleScope<'a>, obj: Local<Object>, name: &str, module: &dyn JSApi) -> () {
         let k = v8::String::new(scope, name).unwrap();
         let tmp = module.js(scope);
         
@@ -24,9 +50,37 @@ impl AvdanAPI {
     }
     
     fn assign_values<'a>(scope: &mut HandleScope<'a>, obj : Local<Object>) -> () {
-        // Self::assign_mod_to_obj(scope, obj, "Clipboard", &AvClipboard {});
-        Self::assign_mod_to_obj(scope, obj, "Debug", &AvDebug {});
-        Self::assign_mod_to_obj(scope, obj, "Shell", &AvShell {});
+        let apis = Self::public_apis();
+
+        for name in Self::GLOBALS {
+            Self::assign_mod_to_obj(scope, obj, &Self::export_name(name), apis[name].as_ref());
+        }
+    }
+
+    /// APIs which are also on the `Avdan` global, for classic scripts.
+    pub const GLOBALS : [&'static str; 4] = ["debug", "secrets", "security", "shell"];
+
+    /// `avdan.d.ts`, generated from the [`JSApi::declaration`] of every API.
+    pub fn typescript() -> String {
+        typings::typescript(Self::public_apis())
+    }
+
+    /// APIs which any extension may import, without a permission.
+    const UNPRIVILEGED : [&'static str; 2] = ["debug", "security"];
+
+    /// Whether importing `@avdan/<name>` requires a permission under `avdan.<name>`.
+    pub fn is_privileged(name: &str) -> bool {
+        !Self::UNPRIVILEGED.contains(&name)
+    }
+
+    /// The name an API is exported as from `@avdan` (and on the `Avdan` global), e.g. `net` -> `Net`.
+    pub fn export_name(name: &str) -> String {
+        let mut chars = name.chars();
+
+        match chars.next() {
+            Some(c) => c.to_uppercase().chain(chars).collect(),
+            None    => String::new(),
+        }
     }
 
     pub fn public_apis<'a>() -> HashMap<&'static str, Box<dyn JSApi>> {
@@ -36,6 +90,8 @@ impl AvdanAPI {
         h.insert("debug", Box::new(AvDebug {}));
         h.insert("shell", Box::new(AvShell {}));
         h.insert("net", Box::new(AvNet {}));
+        h.insert("secrets", Box::new(AvSecrets {}));
+        h.insert("security", Box::new(AvSecurity {}));
         h
     }
 }
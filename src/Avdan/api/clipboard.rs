@@ -339,7 +339,7 @@ impl ClipboardJS {
     }
 
     // Clipboard.copy
-    #[permission(avdan.clipboard.write)]
+    #[permission(avdan.clipboard.write, description = "Write to, or clear, the clipboard.")]
     pub fn copy (
         scope  : &mut v8::HandleScope,
         args   : v8::FunctionCallbackArguments,
@@ -375,7 +375,7 @@ impl ClipboardJS {
     }
 
     // Clipboard.clear
-    #[permission(avdan.clipboard.write)]
+    #[permission(avdan.clipboard.write, description = "Write to, or clear, the clipboard.")]
     pub fn clear(
         scope  : &mut v8::HandleScope,
         args   : v8::FunctionCallbackArguments,
@@ -410,7 +410,7 @@ impl ClipboardJS {
     }
 
     // Clipboard.copyRaw
-    #[permission(avdan.clipboard.write)]
+    #[permission(avdan.clipboard.write, description = "Write to, or clear, the clipboard.")]
     pub fn copy_raw (
         scope  : &mut v8::HandleScope,
         args   : v8::FunctionCallbackArguments,
@@ -516,7 +516,7 @@ impl ClipboardJS {
     }
 
     // Clipboard.readRaw
-    #[permission(avdan.clipboard.read)]
+    #[permission(avdan.clipboard.read, description = "Read the clipboard.")]
     pub fn read_raw (
         scope  : &mut v8::HandleScope,
         args   : v8::FunctionCallbackArguments,
@@ -563,7 +563,7 @@ impl ClipboardJS {
     }
 
     // Clipboard.read
-    #[permission(avdan.clipboard.read)]
+    #[permission(avdan.clipboard.read, description = "Read the clipboard.")]
     pub fn read (
         scope  : &mut v8::HandleScope,
         args   : v8::FunctionCallbackArguments,
@@ -586,7 +586,7 @@ impl ClipboardJS {
     }
 
     // Clipboard.readText
-    #[permission(avdan.clipboard.read)]
+    #[permission(avdan.clipboard.read, description = "Read the clipboard.")]
     pub fn read_text (
         scope  : &mut v8::HandleScope,
         args   : v8::FunctionCallbackArguments,
@@ -603,7 +603,7 @@ impl ClipboardJS {
       }
 
     // Clipboard.formats
-    #[permission(avdan.clipboard.read)]
+    #[permission(avdan.clipboard.read, description = "Read the clipboard.")]
     pub fn formats (
         scope  : &mut v8::HandleScope,
         args   : v8::FunctionCallbackArguments,
@@ -626,7 +626,7 @@ impl ClipboardJS {
       }
 
     // Clipboard.paste
-    #[permission(avdan.clipboard.type)]
+    #[permission(avdan.clipboard.type, description = "Type text into the focused window.")]
     pub fn paste_text (
         scope  : &mut v8::HandleScope,
         args   : v8::FunctionCallbackArguments,
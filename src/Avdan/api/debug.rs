@@ -1,7 +1,9 @@
 use std::time::Duration;
 
+use avdanos_search_macros::signature;
+
 use crate::{
-    core::{def_safe_property, JSApi}, Avdan::{runtime::{task::output, Task, Type}},
+    core::{def_safe_property, JSApi, Declaration}, Avdan::{runtime::{task::output, Task, Type}},
 };
 use colored::*;
 use v8::{
@@ -49,6 +51,13 @@ impl JSApi for AvDebug {
         
         obj
     }
+
+    fn declaration(&self) -> Declaration {
+        Declaration::new()
+            .describe("Debugging helpers.")
+            .function(Self::log__SIGNATURE)
+            .function(Self::wait__SIGNATURE)
+    }
 }
 
 impl AvDebug {
@@ -68,6 +77,7 @@ impl AvDebug {
     }
 
     // Debug.log
+    #[signature(params = "...msg: any[]", returns = "void")]
     pub fn log (
         scope : &mut HandleScope,
         args  : v8::FunctionCallbackArguments,
@@ -95,7 +105,8 @@ impl AvDebug {
         obj.into()
     }
 
-    // Debug.log
+    // Debug.wait
+    #[signature(params = "ticks: number, time: number", returns = "Task<void, { tick: (tick: { tick: number }) => void }>")]
     pub fn wait (
         scope  : &mut HandleScope,
         args   : FunctionCallbackArguments,
@@ -2,7 +2,7 @@ use std::slice;
 
 use v8::{HandleScope, Local, Object, Value, Uint8Array, ArrayBuffer};
 
-use crate::core::{JSApi, AvJSObject, def_safe_function};
+use crate::core::{JSApi, AvJSObject, Declaration, def_safe_function};
 
 /*
     A collection of network functions.
@@ -121,6 +121,13 @@ impl From<Body> for reqwest::blocking::Body {
 // Simplified version of the fetch API
 mod fetch;
 use fetch::Fetch;
+
+const FETCH_OPTIONS_TS : &str = r#"export interface FetchOptions {
+    method?: "GET" | "HEAD" | "POST" | "PUT" | "DELETE" | "CONNECT" | "OPTIONS" | "TRACE" | "PATCH" | string;
+    headers?: Record<string, string>;
+    body?: string | Uint8Array;
+}"#;
+
 pub struct AvNet;
 
 impl AvNet {
@@ -136,4 +143,11 @@ impl JSApi for AvNet {
         
         obj
     }
+
+    fn declaration(&self) -> Declaration {
+        Declaration::new()
+            .describe("Network access.")
+            .types(FETCH_OPTIONS_TS)
+            .function(Fetch::fetch__SIGNATURE)
+    }
 }
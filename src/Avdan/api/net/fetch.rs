@@ -1,21 +1,26 @@
 use std::collections::HashMap;
+use std::io::Read;
 use std::ffi::c_void;
 use std::intrinsics::{transmute,};
 use std::mem::size_of_val;
 use std::ptr;
 use std::slice::{from_raw_parts, self};
 use std::str::FromStr;
-use avdanos_search_macros::permission;
+use avdanos_search_macros::{permission, signature};
 use colored::Colorize;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use v8::{Local, Object, Value, Exception, HandleScope};
 use crate::Avdan;
 use crate::Avdan::runtime::{Task, output};
+use crate::Avdan::security::{Constraints, QUOTA_EXCEEDED};
 use crate::core::obj_has_property;
 use crate::{core::{AvJSObject, obj_get_property}, Avdan::utils::array_to_vec};
 
 use super::{Body, Method};
 
+// Same as reqwest's default policy.
+const MAX_REDIRECTS : usize = 10;
+
 struct Options {
     method  : Method,
     headers : Option<HashMap<String, String>>,
@@ -70,18 +75,9 @@ impl AvJSObject for HashMap<String, String> {
 struct Response;
 
 impl Response {
-    fn from_prom_callback<'a>(scope: &mut HandleScope<'a>, mut vec : Vec<u8>) -> Local<'a, Value> {
-        let s = vec.as_mut_slice();
-
-        // let a = unsafe {
-        //     ptr::read(s as *mut [u8] as *mut reqwest::blocking::Response)
-        // };
-
-        // let txt = a.text().unwrap();
-
-        // let string = v8::String::new(scope, txt.as_str()).unwrap();
-        let udef = v8::undefined(scope);
-        udef.into()
+    fn from_prom_callback<'a>(scope: &mut HandleScope<'a>, vec : Vec<u8>) -> Local<'a, Value> {
+        let txt = String::from_utf8_lossy(&vec);
+        v8::String::new(scope, &txt).unwrap().into()
     }
 }
 
@@ -154,7 +150,8 @@ impl AvJSObject for Options {
 pub struct Fetch;
 
 impl Fetch {
-    #[permission(avdan.net.fetch)]
+    #[signature(params = "uri: string, options?: FetchOptions", returns = "Task<unknown>")]
+    #[permission(avdan.net.fetch, resource = args.get(0).to_rust_string_lossy(scope), description = "Make HTTP requests.")]
     pub fn fetch<'a> (
         scope: &mut v8::HandleScope<'a>,
         args : v8::FunctionCallbackArguments,
@@ -187,12 +184,34 @@ impl Fetch {
 
         let uri = uri.to_rust_string_lossy(scope);
 
+        // Every redirect is checked as the request itself was, so it can't lead out of a scoped permission.
+        let allowed = Constraints::from_scope(scope).resource_checker(scope, "avdan.net.fetch");
+
+        // What's left of the byte quota, if there is one -- held until the request is done,
+        // so requests running side by side share it rather than each getting all of it.
+        let mut reservation = Constraints::from_scope(scope).reserve_bytes(scope, "avdan.net.fetch");
+
         let prom = Task::new (
             scope, 
             move |(id, tx)| {
+                let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+                    if attempt.previous().len() >= MAX_REDIRECTS {
+                        return attempt.error("Too many redirects!");
+                    }
 
-                println!("{}", "#1".blue());
-                let client = reqwest::blocking::Client::new();
+                    match allowed(attempt.url().as_str()) {
+                        true  => attempt.follow(),
+                        false => {
+                            let error = format!("SecurityException -- Redirected to `{}`, which your extension has no permission for!", attempt.url());
+                            attempt.error(error)
+                        },
+                    }
+                });
+
+                let client = reqwest::blocking::Client::builder()
+                    .redirect(redirect)
+                    .build()
+                    .map_err(|e| e.to_string())?;
 
                 let r = client.request (
                     reqwest::Method::from(opts.method), uri);
@@ -208,15 +227,33 @@ impl Fetch {
 
                 }
 
-                println!("{}", "#2".blue());
-
                 let res = r.send();
                 
                 match res {
                     Ok(mut res) => {
-                        res.copy_to(w)
-                        let c = v;
-                        Ok(c.to_vec())
+                        let mut body = Vec::new();
+
+                        // Read at most one byte past the quota, to tell if it's over.
+                        let budget = reservation.as_ref().map(|r| r.budget());
+
+                        let read = match budget {
+                            Some(limit) => (&mut res).take(limit + 1).read_to_end(&mut body),
+                            None        => res.read_to_end(&mut body),
+                        };
+
+                        // What was read counts against the quota, even if the request fails.
+                        if let Some(r) = reservation.as_mut() {
+                            r.used(body.len() as u64);
+                        }
+
+                        read.map_err(|e| e.to_string())?;
+
+                        match budget {
+                            Some(limit) if body.len() as u64 > limit => Err(format!(
+                                "{} -- 'avdan.net.fetch' can only transfer {} more bytes for now.", QUOTA_EXCEEDED, limit
+                            )),
+                            _ => Ok(body),
+                        }
                     },
                     Err(e) => {
                         Err(e.to_string())
@@ -0,0 +1,136 @@
+use avdanos_search_macros::{permission, signature};
+use v8::{HandleScope, Local, Object, FunctionCallbackArguments, PromiseResolver, ReturnValue, Value};
+
+use crate::core::{JSApi, Declaration, def_safe_function};
+use crate::Avdan::{self, runtime::{Builder, Task, output}, security::{SecretAction, Secrets}};
+
+pub struct AvSecrets {}
+
+impl AvSecrets {
+    fn string_arg(scope: &mut HandleScope, args: &FunctionCallbackArguments, i: i32, name: &str) -> Option<String> {
+        if !args.get(i).is_string() {
+            let msg = v8::String::new(scope, format!("Secret {} must be a string!", name).as_str()).unwrap();
+            let exception = v8::Exception::type_error(scope, msg);
+            scope.throw_exception(exception);
+
+            return None;
+        }
+
+        Some(args.get(i).to_rust_string_lossy(scope))
+    }
+
+    // `null` when there's no such secret.
+    fn value<'a>(scope: &mut HandleScope<'a>, vec: Vec<u8>) -> Local<'a, Value> {
+        let value : Option<String> = serde_json::from_slice(&vec).unwrap();
+
+        match value {
+            Some(v) => v8::String::new(scope, &v).unwrap().into(),
+            None    => v8::null(scope).into(),
+        }
+    }
+
+    fn deleted<'a>(scope: &mut HandleScope<'a>, vec: Vec<u8>) -> Local<'a, Value> {
+        let value : Option<String> = serde_json::from_slice(&vec).unwrap();
+
+        v8::Boolean::new(scope, value.is_some()).into()
+    }
+
+    // The secrets are always the running extension's, whatever JS asks for.
+    fn run<'a>(scope: &mut HandleScope<'a>, action: SecretAction, builder: Builder) -> Local<'a, PromiseResolver> {
+        let identity = Secrets::from_scope(scope);
+
+        Task::new(
+            scope,
+            move |_| {
+                let value = Secrets::run(&identity, action)?;
+                Ok(serde_json::to_vec(&value).unwrap())
+            },
+            builder
+        )
+    }
+
+    // Secrets.get(key : string) -> Task<string | null>
+    #[signature(params = "key: string", returns = "Task<string | null>")]
+    #[permission(avdan.secrets, description = "Store secrets (API tokens...), encrypted, which only the extension can read.")]
+    pub fn get (
+        scope  : &mut HandleScope,
+        args   : FunctionCallbackArguments,
+        mut rv : ReturnValue
+    ) -> () {
+        let key = match Self::string_arg(scope, &args, 0, "key") {
+            Some(k) => k,
+            None    => return,
+        };
+
+        let prom = Self::run(scope, SecretAction::Get { key }, Self::value);
+        rv.set(prom.into());
+    }
+
+    // Secrets.set(key : string, value : string) -> Task<void>
+    #[signature(params = "key: string, value: string", returns = "Task<void>")]
+    #[permission(avdan.secrets)]
+    pub fn set (
+        scope  : &mut HandleScope,
+        args   : FunctionCallbackArguments,
+        mut rv : ReturnValue
+    ) -> () {
+        let key = match Self::string_arg(scope, &args, 0, "key") {
+            Some(k) => k,
+            None    => return,
+        };
+
+        let value = match Self::string_arg(scope, &args, 1, "value") {
+            Some(v) => v,
+            None    => return,
+        };
+
+        let prom = Self::run(scope, SecretAction::Set { key, value }, output::void);
+        rv.set(prom.into());
+    }
+
+    // Secrets.delete(key : string) -> Task<boolean>
+    #[signature(params = "key: string", returns = "Task<boolean>")]
+    #[permission(avdan.secrets)]
+    pub fn delete (
+        scope  : &mut HandleScope,
+        args   : FunctionCallbackArguments,
+        mut rv : ReturnValue
+    ) -> () {
+        let key = match Self::string_arg(scope, &args, 0, "key") {
+            Some(k) => k,
+            None    => return,
+        };
+
+        let prom = Self::run(scope, SecretAction::Delete { key }, Self::deleted);
+        rv.set(prom.into());
+    }
+
+    fn assign_functions<'a> (
+        scope : &mut HandleScope<'a>,
+        obj   : Local<Object>
+    ) -> () {
+        def_safe_function!(scope, obj, "get", Self::get);
+        def_safe_function!(scope, obj, "set", Self::set);
+        def_safe_function!(scope, obj, "delete", Self::delete);
+    }
+}
+
+impl JSApi for AvSecrets {
+    fn js<'a> (
+        &self,
+        scope: &mut v8::HandleScope<'a>
+    ) -> Local<'a, Object> {
+        let obj = Object::new(scope);
+        Self::assign_functions(scope, obj);
+
+        obj
+    }
+
+    fn declaration(&self) -> Declaration {
+        Declaration::new()
+            .describe("Stores the extension's secrets, encrypted -- no other extension can read them.")
+            .function(Self::get__SIGNATURE)
+            .function(Self::set__SIGNATURE)
+            .function(Self::delete__SIGNATURE)
+    }
+}
@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use avdanos_search_macros::signature;
+use serde::{Serialize, Deserialize};
+use v8::{HandleScope, Local, Object, FunctionCallbackArguments, ReturnValue, Value};
+
+use crate::core::{JSApi, Declaration, def_safe_function};
+use crate::Avdan::{Permission, runtime::Task, security::{Constraints, Consent, ConsentProvider, Grant, Grants}};
+
+/// What a consent request came to, sent from the task's thread back to the runtime.
+#[derive(Serialize, Deserialize)]
+struct Outcome {
+    allowed : bool,
+    /// A new grant, to be remembered.
+    grant   : Option<Grant>,
+}
+
+pub struct AvSecurity {}
+
+impl AvSecurity {
+    fn permission_arg(scope: &mut HandleScope, args: &FunctionCallbackArguments) -> Option<String> {
+        if !args.get(0).is_string() {
+            let msg = v8::String::new(scope, "Permission must be a string!").unwrap();
+            let exception = v8::Exception::type_error(scope, msg);
+            scope.throw_exception(exception);
+
+            return None;
+        }
+
+        Some(args.get(0).to_rust_string_lossy(scope))
+    }
+
+    fn outcome<'a>(scope: &mut HandleScope<'a>, vec: Vec<u8>) -> Local<'a, Value> {
+        let outcome : Outcome = serde_json::from_slice(&vec).unwrap();
+
+        if let (Some(grant), Some(grants)) = (outcome.grant, Grants::from_scope(scope)) {
+            grants.add(grant);
+
+            if let Err(err) = grants.save() {
+                println!("Could not save permission grants: {}", err);
+            }
+        }
+
+        v8::Boolean::new(scope, outcome.allowed).into()
+    }
+
+    // Security.request(permission : string) -> Task<boolean>
+    #[signature(params = "permission: string", returns = "Task<boolean>")]
+    pub fn request (
+        scope  : &mut HandleScope,
+        args   : FunctionCallbackArguments,
+        mut rv : ReturnValue
+    ) -> () {
+        let perm = match Self::permission_arg(scope, &args) {
+            Some(p) => p,
+            None    => return,
+        };
+
+        if let Err(err) = Grant::validate_request(&perm) {
+            let msg = v8::String::new(scope, err.as_str()).unwrap();
+            let exception = v8::Exception::type_error(scope, msg);
+            scope.throw_exception(exception);
+
+            return;
+        }
+
+        // An unscoped permission covers any scoped request of it.
+        let allowed   = Constraints::from_scope(scope).allows(scope, Permission::new(&perm).name());
+        let extension = Grants::from_scope(scope).map(|g| g.extension().to_string()).unwrap_or_default();
+
+        // Don't ask the user for what the policy forbids anyway.
+        let provider  = scope.get_slot::<Arc<dyn ConsentProvider>>().cloned()
+            .filter(|_| Constraints::from_scope(scope).is_within_policy(Permission::new(&perm).name()));
+
+        let prom = Task::new(
+            scope,
+            move |_| {
+                let outcome = match (allowed, provider) {
+                    (true, _)            => Outcome { allowed: true, grant: None },
+                    (false, None)        => Outcome { allowed: false, grant: None },
+                    (false, Some(p))     => match p.request(&extension, &perm) {
+                        Consent::Deny           => Outcome { allowed: false, grant: None },
+                        Consent::Allow(expires) => Outcome { allowed: true, grant: Some(Grant::new(&perm, expires)) },
+                    },
+                };
+
+                Ok(serde_json::to_vec(&outcome).unwrap())
+            },
+            Self::outcome
+        );
+
+        rv.set(prom.into());
+    }
+
+    // Security.revoke(permission : string) -> boolean
+    #[signature(params = "permission: string", returns = "boolean")]
+    pub fn revoke (
+        scope  : &mut HandleScope,
+        args   : FunctionCallbackArguments,
+        mut rv : ReturnValue
+    ) -> () {
+        let perm = match Self::permission_arg(scope, &args) {
+            Some(p) => p,
+            None    => return,
+        };
+
+        let revoked = match Grants::from_scope(scope) {
+            Some(grants) if grants.revoke(&perm) => grants.save().is_ok(),
+            _                                    => false,
+        };
+
+        rv.set(v8::Boolean::new(scope, revoked).into());
+    }
+
+    fn assign_functions<'a> (
+        scope : &mut HandleScope<'a>,
+        obj   : Local<Object>
+    ) -> () {
+        def_safe_function!(scope, obj, "request", Self::request);
+        def_safe_function!(scope, obj, "revoke", Self::revoke);
+    }
+}
+
+impl JSApi for AvSecurity {
+    fn js<'a> (
+        &self, 
+        scope: &mut v8::HandleScope<'a>
+    ) -> Local<'a, Object> {
+        let obj = Object::new(scope);
+        Self::assign_functions(scope, obj);
+        
+        obj
+    }
+
+    fn declaration(&self) -> Declaration {
+        Declaration::new()
+            .describe("Asks the user for permissions the manifest doesn't declare, and gives them back.")
+            .function(Self::request__SIGNATURE)
+            .function(Self::revoke__SIGNATURE)
+    }
+}
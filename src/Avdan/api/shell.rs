@@ -1,44 +1,129 @@
-use std::io::{Read, self, Write};
+use std::env;
+use std::io;
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
 
-use avdanos_search_macros::permission;
-use v8::{HandleScope, Local, Object, FunctionCallbackArguments, ReturnValue};
+use avdanos_search_macros::{permission, signature};
+use serde::Serialize;
+use v8::{HandleScope, Local, Object, FunctionCallbackArguments, ReturnValue, Value};
+
+use crate::core::{JSApi, Declaration, def_safe_function, obj_get_property};
+use crate::Avdan::{self, runtime::Task, security::Constraints};
+
+const SHELL_TS : &str = r#"export interface ShellOptions {
+    /** Working directory, must be allowed by the command's `cwd` declaration. */
+    cwd?: string;
+}
+
+export interface ShellResult {
+    status: number | null;
+    stdout: string;
+    stderr: string;
+}"#;
+
+#[derive(Serialize)]
+struct ShellResult {
+    status : Option<i32>,
+    stdout : String,
+    stderr : String,
+}
 
-use crate::core::{JSApi, def_safe_function};
-use crate::Avdan;
 pub struct AvShell {}
 
 impl AvShell {
-    // Shell.exec(cmd : string, ...args : string[]) -> ShellResult
-    #[permission(avdan.shell.exec)]
+    fn type_error(scope: &mut HandleScope, message: &str) -> () {
+        let msg = v8::String::new(scope, message).unwrap();
+        let exception = v8::Exception::type_error(scope, msg);
+        scope.throw_exception(exception);
+    }
+
+    fn result<'a>(scope: &mut HandleScope<'a>, vec: Vec<u8>) -> Local<'a, Value> {
+        let json = v8::String::new(scope, String::from_utf8(vec).unwrap().as_str()).unwrap();
+        v8::json::parse(scope, json).unwrap()
+    }
+
+    // Shell.exec(cmd : string, ...args : string[], options? : ShellOptions) -> Task<ShellResult>
+    #[signature(params = "cmd: string, ...args: (string | ShellOptions)[]", returns = "Task<ShellResult>")]
+    #[permission(avdan.shell.exec, description = "Run the commands declared in `security.commands`.")]
     pub fn exec (
         scope  : &mut HandleScope,
         args   : FunctionCallbackArguments,
         mut rv : ReturnValue
     ) -> () {
         if args.length() == 0 {
-            let msg = v8::String::new(scope,"No args provided!").unwrap();
-            let exception = v8::Exception::type_error(scope, msg);
-            scope.throw_exception(exception);
-            
+            Self::type_error(scope, "No args provided!");
             return;
         }
 
         let mut cmd_args : Vec<String> = Vec::new();
+        let mut cwd = env::current_dir().unwrap_or(PathBuf::from("/"));
 
         for i in 0..args.length() {
-            if !args.get(i).is_string() {
-                let msg = v8::String::new(scope,"Args must all be strings!").unwrap();
-                let exception = v8::Exception::type_error(scope, msg);
-                scope.throw_exception(exception);
-                
+            let arg = args.get(i);
+
+            // Trailing options object.
+            if i == args.length() - 1 && i > 0 && arg.is_object() && !arg.is_string() {
+                let options : Local<Object> = arg.try_into().unwrap();
+                let dir = obj_get_property(scope, options, "cwd");
+
+                if !dir.is_undefined() {
+                    if !dir.is_string() {
+                        Self::type_error(scope, "ShellOptions.cwd must be a string!");
+                        return;
+                    }
+
+                    cwd = cwd.join(dir.to_rust_string_lossy(scope));
+                }
+
+                break;
+            }
+
+            if !arg.is_string() {
+                Self::type_error(scope, "Args must all be strings!");
                 return;
             }
             
-            cmd_args.push(args.get(i).to_rust_string_lossy(scope));
+            cmd_args.push(arg.to_rust_string_lossy(scope));
         }
 
-        rv.set(v8::undefined(scope).into());
+        let cwd = cwd.canonicalize().unwrap_or(cwd);
+        let cmd = cmd_args.remove(0);
+
+        // Validate the whole invocation before anything is spawned.
+        let declaration = match Constraints::from_scope(scope).throw_command_exception(scope, &cmd, &cmd_args, &cwd) {
+            Some(d) => d.clone(),
+            None    => return,
+        };
+
+        let prom = Task::new(
+            scope,
+            move |_| {
+                let mut command = Command::new(&cmd);
+
+                command.args(&cmd_args)
+                    .current_dir(&cwd)
+                    .stdin(Stdio::null());
+
+                declaration.apply(&mut command);
+
+                // Declared commands aren't checked when the manifest is loaded, a missing one shows up here.
+                let out = command.output().map_err(|e| match e.kind() {
+                    io::ErrorKind::NotFound => format!("Could not run `{}`: it is not installed (or not on PATH).", cmd),
+                    _                       => format!("Could not run `{}`: {}", cmd, e),
+                })?;
+
+                let result = ShellResult {
+                    status : out.status.code(),
+                    stdout : String::from_utf8_lossy(&out.stdout).to_string(),
+                    stderr : String::from_utf8_lossy(&out.stderr).to_string(),
+                };
+
+                Ok(serde_json::to_vec(&result).unwrap())
+            },
+            Self::result
+        );
+
+        rv.set(prom.into());
     }
 
     fn assign_functions<'a> (
@@ -59,6 +144,13 @@ impl JSApi for AvShell {
         
         obj
     }
+
+    fn declaration(&self) -> Declaration {
+        Declaration::new()
+            .describe("Runs the shell commands declared in the extension's manifest.")
+            .types(SHELL_TS)
+            .function(Self::exec__SIGNATURE)
+    }
 }
 
 struct ShellObj {
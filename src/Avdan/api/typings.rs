@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use crate::core::{JSApi, Declaration, Signature};
+
+use super::AvdanAPI;
+
+const HEADER : &str = "// Generated by `proj types` -- do not edit, regenerate it instead.\n";
+
+const TASK_TS : &str = r#"    export interface Task<R, E extends Task.Event = {}> extends Promise<R> {
+        on<N extends keyof E>(name: N, callback: E[N]): Task<R, E>;
+    }
+
+    export namespace Task {
+        export interface Event {
+            [name: string]: (...args: any[]) => void;
+        }
+    }"#;
+
+fn indent(text: &str, by: usize) -> String {
+    text.lines()
+        .map(|l| match l.is_empty() {
+            true  => String::new(),
+            false => format!("{}{}", " ".repeat(by), l),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn function(f: &Signature) -> String {
+    let doc = match f.permission {
+        Some(p) => format!("        /** Requires `{}`. */\n", p),
+        None    => String::new(),
+    };
+
+    format!("{}        {}({}): {};", doc, f.name, f.params, f.returns)
+}
+
+fn api_module(name: &str, declaration: &Declaration) -> String {
+    let interface = AvdanAPI::export_name(name);
+    let mut out = vec![];
+
+    if let Some(d) = declaration.description {
+        out.push(format!("/** {} */", d));
+    }
+
+    out.push(format!("declare module '@avdan/{}' {{", name));
+    out.push("    import { Task } from '@avdan';\n".to_string());
+
+    for ts in declaration.types.iter() {
+        out.push(indent(ts, 4) + "\n");
+    }
+
+    out.push(format!("    export interface {} {{", interface));
+    out.push(declaration.functions.iter().map(function).collect::<Vec<_>>().join("\n\n"));
+    out.push("    }\n".to_string());
+    out.push(format!("    const api: {};", interface));
+    out.push("    export default api;".to_string());
+    out.push("}".to_string());
+
+    // `@avdan/<api>/<function>`
+    for f in declaration.functions.iter() {
+        out.push(format!(
+            "\ndeclare module '@avdan/{0}/{1}' {{\n    const {1}: import('@avdan/{0}').{2}['{1}'];\n    export default {1};\n}}",
+            name, f.name, interface
+        ));
+    }
+
+    out.join("\n")
+}
+
+/// Builds `avdan.d.ts` from the declarations of `apis`.
+pub fn typescript(apis: HashMap<&'static str, Box<dyn JSApi>>) -> String {
+    let mut apis : Vec<_> = apis.into_iter().collect();
+    apis.sort_by_key(|(name, _)| *name);
+
+    let mut root = vec![TASK_TS.to_string()];
+
+    for (name, _) in apis.iter() {
+        root.push(format!("    export const {}: import('@avdan/{}').{};", AvdanAPI::export_name(name), name, AvdanAPI::export_name(name)));
+    }
+
+    let mut out = vec![
+        HEADER.to_string(),
+        format!("declare module '@avdan' {{\n{}\n}}", root.join("\n\n")),
+    ];
+
+    for (name, api) in apis.iter() {
+        out.push(api_module(name, &api.declaration()));
+    }
+
+    let globals : Vec<String> = AvdanAPI::GLOBALS.iter()
+        .map(|name| format!("    {}: import('@avdan/{}').{};", AvdanAPI::export_name(name), name, AvdanAPI::export_name(name)))
+        .collect();
+
+    out.push(format!("/** The APIs available to classic scripts. */\ndeclare const Avdan: {{\n{}\n}};", globals.join("\n")));
+
+    out.join("\n\n") + "\n"
+}
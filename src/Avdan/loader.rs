@@ -1,8 +1,13 @@
-use std::{path::Path, };
+use std::{path::{Path, PathBuf}, };
 use serde::{Serialize, Deserialize};
-use super::security::Constraints;
+use super::security::{Constraints, Overrides, Policy};
 
-const MANIFEST_FILE : &str = "manifest.avdan.json";
+mod package;
+mod signature;
+pub use package::{Package, PACKAGE_EXTENSION};
+pub use signature::{ExtensionSignature, Keyring, Verification, SIGNATURE_FILE};
+
+pub const MANIFEST_FILE : &str = "manifest.avdan.json";
 
 #[derive(Serialize, Deserialize)]
 pub struct Extension {
@@ -12,20 +17,27 @@ pub struct Extension {
     author      : String,
     main        : String,
     security    : super::security::Constraints,
+    #[serde(skip)]
+    verification : Verification,
+    /// What the policy removed from the manifest's constraints.
+    #[serde(skip)]
+    restricted   : Vec<String>,
+    /// Where the extension was loaded from (its folder, or its package).
+    #[serde(skip)]
+    source       : PathBuf,
 }
 
 impl Extension {
-    fn is_folder(path : &str) -> bool {
-        let m = std::fs::metadata(path);
+    fn is_folder(path : &str) -> Result<bool, String> {
+        let m = std::fs::metadata(path).map_err(|_| "Folder does not exist!".to_string())?;
 
-        m.expect("Folder does not exist!").is_dir()
+        Ok(m.is_dir())
     }
 
-    fn load_manifest(path : &str) -> String {
-        let dir = Path::new(path);
-        let manif_file = std::fs::read(dir.join(MANIFEST_FILE));
+    fn load_manifest(dir : &Path) -> Result<String, String> {
+        let manif_file = Package::read(&dir.join(MANIFEST_FILE)).map_err(|_| "Mainifest file does not exist!".to_string())?;
 
-        String::from_utf8(manif_file.expect("Mainifest file does not exist!")).expect("Could not decode file!")
+        String::from_utf8(manif_file).map_err(|_| "Could not decode file!".to_string())
     }
 
     fn parse_manifest<'s>(content: String) -> Result<Extension, serde_json::Error> {
@@ -34,7 +46,7 @@ impl Extension {
 
     ///
     /// Parse a `manifest.avdan.json` file into an [`Extension`] struct.
-    /// * `path` - Extension's root directory
+    /// * `path` - Extension's root directory, or its package (see [`Package`])
     /// 
     /// ## Example
     /// 
@@ -45,16 +57,40 @@ impl Extension {
     ///
     
     pub fn from_manifest(path: &str) -> Extension {
-        if !Self::is_folder(path) {
-            panic!("Must provide folder!");
+        Self::load(path, &Overrides::default())
+    }
+
+    /// Like [`Extension::from_manifest`], with the command line's permission overrides (see [`Overrides`]).
+    pub fn load(path: &str, overrides: &Overrides) -> Extension {
+        Self::try_load(path, overrides).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like [`Extension::load`], returning what's wrong instead of panicking (e.g. for `--watch` reloads).
+    pub fn try_load(path: &str, overrides: &Overrides) -> Result<Extension, String> {
+        let root = match Self::is_folder(path)? {
+            true  => PathBuf::from(path),
+            false => Package::open(path).map_err(|err| format!("Failed to open package!\n{}", err))?,
+        };
+
+        let text = Self::load_manifest(&root)?;
+        let mut e = Self::parse_manifest(text).map_err(|err| format!("Failed to parse manifest file!\n{}", err))?;
+
+        if let Err(err) = e.security.validate() {
+            return Err(format!("Invalid manifest `{}`!\n{}", root.join(MANIFEST_FILE).to_str().unwrap(), err));
         }
 
-        let text = Self::load_manifest(path);
-        let mut e = Self::parse_manifest(text).expect("Failed to parse manifest file!");
+        // Who it is decides which policy rules apply.
+        e.verification = ExtensionSignature::verify(&root);
+        e.source = PathBuf::from(path).canonicalize().unwrap_or(PathBuf::from(path));
+
+        let mut policy = Policy::load()?;
+        overrides.apply(&mut e.security, &mut policy);
+
+        (e.security, e.restricted) = policy.apply(&e.identity(), e.security.clone());
+
+        e.main = root.join(e.main).to_str().unwrap().to_string();
 
-        e.main = Path::new(path).join(e.main).to_str().unwrap().to_string();
-        
-        return e;
+        return Ok(e);
     }
 
     ///
@@ -69,7 +105,31 @@ impl Extension {
         return &self.main;
     }
 
+    pub fn name(&self) -> &String {
+        return &self.name;
+    }
+
     pub fn security(&self) -> &Constraints {
         return &self.security;
     }
+
+    /// What the system/user policy removed from the manifest (see [`Policy`]).
+    pub fn restricted(&self) -> &Vec<String> {
+        return &self.restricted;
+    }
+
+    /// Whether (and by whom) the extension was signed -- see [`ExtensionSignature`].
+    pub fn verification(&self) -> &Verification {
+        return &self.verification;
+    }
+
+    /// Who the extension is, beyond its (self-chosen) name --
+    /// its trusted publisher if it's verified, or else where it was loaded from.
+    /// Anything kept per extension which others mustn't reach (e.g. [`crate::Avdan::security::Secrets`]) is keyed by it.
+    pub fn identity(&self) -> String {
+        match &self.verification {
+            Verification::Verified { publisher } => format!("{}@{}", self.name, publisher),
+            _                                    => format!("{}@{}", self.name, self.source.to_string_lossy()),
+        }
+    }
 }
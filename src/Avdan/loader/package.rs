@@ -0,0 +1,338 @@
+use std::{collections::{HashMap, BTreeMap}, path::{Component, Path, PathBuf}, fs, io::{self, Read, Write}, sync::Mutex};
+
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use zip::{ZipArchive, ZipWriter, write::FileOptions};
+
+use super::{MANIFEST_FILE, SIGNATURE_FILE};
+
+pub const INTEGRITY_FILE : &str = "integrity.avdan.json";
+pub const PACKAGE_EXTENSION : &str = "avdan";
+
+// Every file of every mounted package, keyed by its path
+// *inside* the package (e.g. `/home/me/ext.avdan/Main.js`).
+static MOUNTED : Mutex<Option<HashMap<PathBuf, Vec<u8>>>> = Mutex::new(None);
+
+// The (virtual) root folder of every mounted package.
+static ROOTS : Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+///
+/// ## integrity.avdan.json
+///
+/// The SHA-256 (hex) of every other file in a package, keyed by its path in the archive.
+///
+
+#[derive(Serialize, Deserialize)]
+pub struct Integrity {
+    files : BTreeMap<String, String>,
+}
+
+///
+/// ## Package
+///
+/// A single-file extension: a zip archive with `manifest.avdan.json`
+/// and `integrity.avdan.json` at its root.
+///
+/// Opening a package verifies every file against its integrity entry,
+/// then *mounts* it, so the loader can read its sources as if
+/// the archive were the extension's folder.
+///
+/// Once a package is mounted, nothing outside it can be read through [`Package::read`] --
+/// a (signed) package can't pull in unverified code with `../` or an absolute path.
+///
+
+pub struct Package {}
+
+impl Package {
+    pub fn hash(bytes: &[u8]) -> String {
+        hex::encode(Sha256::digest(bytes))
+    }
+
+    fn read_archive(path: &Path) -> Result<HashMap<String, Vec<u8>>, String> {
+        let file = fs::File::open(path).map_err(|e| e.to_string())?;
+        let mut archive = ZipArchive::new(file).map_err(|e| format!("Not a valid package: {}", e))?;
+        let mut files = HashMap::new();
+
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).map_err(|e| e.to_string())?;
+
+            if entry.is_dir() {
+                continue;
+            }
+
+            // Rejects absolute paths and `..` components.
+            let name = match entry.enclosed_name() {
+                Some(n) => n.to_str().unwrap().replace("\\", "/"),
+                None    => return Err(format!("Package contains an unsafe path `{}`!", entry.name())),
+            };
+
+            let mut contents = vec![];
+            entry.read_to_end(&mut contents).map_err(|e| e.to_string())?;
+
+            files.insert(name, contents);
+        }
+
+        Ok(files)
+    }
+
+    /// Checks that every file has an integrity entry which matches its contents,
+    /// and that every integrity entry has a file.
+    fn verify(files: &HashMap<String, Vec<u8>>) -> Result<(), String> {
+        let integrity = files.get(INTEGRITY_FILE)
+            .ok_or_else(|| format!("Package does not have an `{}`!", INTEGRITY_FILE))?;
+
+        let integrity : Integrity = serde_json::from_slice(integrity)
+            .map_err(|e| format!("Failed to parse `{}`: {}", INTEGRITY_FILE, e))?;
+
+        for (name, contents) in files.iter().filter(|(n, _)| n.as_str() != INTEGRITY_FILE) {
+            match integrity.files.get(name) {
+                None                                      => return Err(format!("`{}` has no integrity entry!", name)),
+                Some(h) if *h != Self::hash(contents) => return Err(format!("`{}` does not match its integrity hash!", name)),
+                Some(_)                                   => {}
+            }
+        }
+
+        if let Some(missing) = integrity.files.keys().find(|n| !files.contains_key(*n)) {
+            return Err(format!("`{}` is missing from the package!", missing));
+        }
+
+        Ok(())
+    }
+
+    /// Verifies and mounts a package, returning the path of its (virtual) root folder.
+    pub fn open(path: &str) -> Result<PathBuf, String> {
+        let root = Path::new(path).canonicalize().map_err(|e| e.to_string())?;
+        let files = Self::read_archive(&root)?;
+
+        Self::verify(&files)?;
+
+        if !files.contains_key(MANIFEST_FILE) {
+            return Err(format!("Package does not have a `{}` at its root!", MANIFEST_FILE));
+        }
+
+        let mut mounted = MOUNTED.lock().unwrap();
+        let mounted = mounted.get_or_insert_with(HashMap::new);
+
+        for (name, contents) in files {
+            mounted.insert(root.join(name), contents);
+        }
+
+        let mut roots = ROOTS.lock().unwrap();
+
+        if !roots.contains(&root) {
+            roots.push(root.clone());
+        }
+
+        Ok(root)
+    }
+
+    /// Creates a package from an extension's folder.
+    pub fn pack(dir: &str, out: &str) -> Result<(), String> {
+        let dir = Path::new(dir);
+
+        if !dir.join(MANIFEST_FILE).exists() {
+            return Err(format!("`{}` does not have a `{}`!", dir.to_str().unwrap(), MANIFEST_FILE));
+        }
+
+        let mut files = BTreeMap::new();
+        Self::collect(dir, dir, &mut files).map_err(|e| e.to_string())?;
+
+        let integrity = Integrity {
+            files : files.iter().map(|(n, c)| (n.clone(), Self::hash(c))).collect()
+        };
+
+        let mut zip = ZipWriter::new(fs::File::create(out).map_err(|e| e.to_string())?);
+
+        for (name, contents) in files.iter() {
+            zip.start_file(name, FileOptions::default()).map_err(|e| e.to_string())?;
+            zip.write_all(contents).map_err(|e| e.to_string())?;
+        }
+
+        zip.start_file(INTEGRITY_FILE, FileOptions::default()).map_err(|e| e.to_string())?;
+        zip.write_all(serde_json::to_string_pretty(&integrity).unwrap().as_bytes()).map_err(|e| e.to_string())?;
+
+        zip.finish().map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    fn collect(root: &Path, dir: &Path, files: &mut BTreeMap<String, Vec<u8>>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let name = path.strip_prefix(root).unwrap().to_str().unwrap().replace("\\", "/");
+
+            // Hidden files too -- the loader can read (and import) them, so they're packed and signed like any other.
+            if name == INTEGRITY_FILE {
+                continue;
+            }
+
+            match path.is_dir() {
+                true  => Self::collect(root, &path, files)?,
+                false => { files.insert(name, fs::read(&path)?); },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Every file of an extension (besides its integrity and signature files),
+    /// keyed by its path relative to `root` -- from a mounted package, or from disk.
+    pub fn files(root: &Path) -> io::Result<BTreeMap<String, Vec<u8>>> {
+        let mut files = BTreeMap::new();
+
+        match MOUNTED.lock().unwrap().as_ref().filter(|m| m.keys().any(|f| f.starts_with(root))) {
+            Some(mounted) => {
+                for (path, contents) in mounted.iter().filter(|(f, _)| f.starts_with(root)) {
+                    let name = path.strip_prefix(root).unwrap().to_str().unwrap().replace("\\", "/");
+                    files.insert(name, contents.clone());
+                }
+            },
+            None => Self::collect(root, root, &mut files)?,
+        }
+
+        files.remove(INTEGRITY_FILE);
+        files.remove(SIGNATURE_FILE);
+
+        Ok(files)
+    }
+
+    /// Resolves `.` and `..` without touching the disk (package paths don't exist there).
+    fn normalize(path: &Path) -> PathBuf {
+        let mut out = PathBuf::new();
+
+        for c in path.components() {
+            match c {
+                Component::ParentDir => { out.pop(); },
+                Component::CurDir    => {},
+                c                    => out.push(c),
+            }
+        }
+
+        out
+    }
+
+    fn mounted(path: &Path) -> Option<Vec<u8>> {
+        MOUNTED.lock().unwrap().as_ref()?.get(&Self::normalize(path)).cloned()
+    }
+
+    fn is_mounted() -> bool {
+        !ROOTS.lock().unwrap().is_empty()
+    }
+
+    fn in_package(path: &Path) -> bool {
+        let path = Self::normalize(path);
+        ROOTS.lock().unwrap().iter().any(|r| path.starts_with(r))
+    }
+
+    /// Reads a file of an extension -- from a mounted package, or from disk.
+    pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+        if let Some(contents) = Self::mounted(path) {
+            return Ok(contents);
+        }
+
+        match (Self::is_mounted(), Self::in_package(path)) {
+            (false, _)    => fs::read(path),
+            (true, true)  => Err(io::Error::new(io::ErrorKind::NotFound, format!("`{}` is not in the package!", path.to_string_lossy()))),
+            (true, false) => Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("`{}` is outside the package!", path.to_string_lossy()))),
+        }
+    }
+
+    pub fn exists(path: &Path) -> bool {
+        match Self::is_mounted() {
+            true  => Self::mounted(path).is_some(),
+            false => path.exists(),
+        }
+    }
+
+    /// Same as [`Path::canonicalize`], but leaves paths inside packages as they are (bar `.`/`..`).
+    pub fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+        match Self::in_package(path) {
+            true  => Ok(Self::normalize(path)),
+            false => path.canonicalize(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, env, fs};
+
+    use super::{Package, INTEGRITY_FILE};
+    use crate::Avdan::loader::MANIFEST_FILE;
+
+    fn files(entries: &[(&str, &str)], hashes: &[(&str, &str)]) -> HashMap<String, Vec<u8>> {
+        let mut files : HashMap<String, Vec<u8>> = entries.iter()
+            .map(|(n, c)| (n.to_string(), c.as_bytes().to_vec()))
+            .collect();
+
+        let integrity = serde_json::json!({
+            "files": hashes.iter()
+                .map(|(n, c)| (n.to_string(), Package::hash(c.as_bytes())))
+                .collect::<HashMap<_, _>>()
+        });
+
+        files.insert(INTEGRITY_FILE.to_string(), integrity.to_string().into_bytes());
+        files
+    }
+
+    #[test]
+    fn intact_packages_verify() {
+        let entries = [(MANIFEST_FILE, "{}"), ("Main.js", "export default 1;")];
+
+        assert!(Package::verify(&files(&entries, &entries)).is_ok());
+    }
+
+    #[test]
+    fn tampered_files_are_rejected() {
+        let hashed = [(MANIFEST_FILE, "{}"), ("Main.js", "export default 1;")];
+        let shipped = [(MANIFEST_FILE, "{}"), ("Main.js", "export default 2;")];
+
+        let err = Package::verify(&files(&shipped, &hashed)).unwrap_err();
+        assert!(err.contains("`Main.js` does not match its integrity hash"), "{}", err);
+    }
+
+    #[test]
+    fn files_without_an_integrity_hash_are_rejected() {
+        let hashed = [(MANIFEST_FILE, "{}")];
+        let shipped = [(MANIFEST_FILE, "{}"), (".hidden.js", "export default 1;")];
+
+        let err = Package::verify(&files(&shipped, &hashed)).unwrap_err();
+        assert!(err.contains("`.hidden.js` has no integrity entry"), "{}", err);
+    }
+
+    #[test]
+    fn removed_files_are_rejected() {
+        let hashed = [(MANIFEST_FILE, "{}"), ("Main.js", "export default 1;")];
+        let shipped = [(MANIFEST_FILE, "{}")];
+
+        let err = Package::verify(&files(&shipped, &hashed)).unwrap_err();
+        assert!(err.contains("`Main.js` is missing"), "{}", err);
+    }
+
+    #[test]
+    fn packages_need_an_integrity_file() {
+        let mut files = files(&[(MANIFEST_FILE, "{}")], &[(MANIFEST_FILE, "{}")]);
+        files.remove(INTEGRITY_FILE);
+
+        assert!(Package::verify(&files).is_err());
+    }
+
+    #[test]
+    fn packed_extensions_verify() {
+        let dir = env::temp_dir().join(format!("avdan-js-pack-{}", std::process::id()));
+        let out = dir.with_extension("avdan");
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join(MANIFEST_FILE), "{}").unwrap();
+        fs::write(dir.join("lib/util.js"), "export default 1;").unwrap();
+        fs::write(dir.join(".env.js"), "export default 2;").unwrap();
+
+        Package::pack(dir.to_str().unwrap(), out.to_str().unwrap()).unwrap();
+        let files = Package::read_archive(&out).unwrap();
+
+        assert!(files.contains_key("lib/util.js"));
+        assert!(files.contains_key(".env.js"));
+        assert!(Package::verify(&files).is_ok());
+    }
+}
@@ -0,0 +1,318 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}};
+
+use colored::Colorize;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+
+use super::{Package, MANIFEST_FILE};
+
+pub const SIGNATURE_FILE : &str = "signature.avdan.json";
+const KEYRING_FILE : &str = "avdan-js/keyring.json";
+
+/// What's actually signed: the manifest's hash, and the hash of every file.
+#[derive(Serialize)]
+struct Payload<'a> {
+    publisher : &'a str,
+    manifest  : &'a str,
+    files     : &'a BTreeMap<String, String>,
+}
+
+///
+/// ## signature.avdan.json
+///
+/// An Ed25519 signature over the extension's manifest and the SHA-256 (hex) of each of its files.
+///
+
+#[derive(Serialize, Deserialize)]
+pub struct ExtensionSignature {
+    pub publisher  : String,
+    /// Hex.
+    pub public_key : String,
+    pub files      : BTreeMap<String, String>,
+    /// Hex.
+    pub signature  : String,
+}
+
+/// The outcome of checking an extension's signature.
+#[derive(Clone)]
+pub enum Verification {
+    Unsigned,
+    /// Signed by a key in the keyring.
+    Verified { publisher: String },
+    /// Correctly signed, by a key which isn't in the keyring.
+    Untrusted { publisher: String, public_key: String },
+    /// The signature doesn't match (or a file was added, changed or removed since signing).
+    Tampered(String),
+}
+
+impl Default for Verification {
+    fn default() -> Self {
+        Verification::Unsigned
+    }
+}
+
+impl Verification {
+    pub fn is_verified(&self) -> bool {
+        matches!(self, Verification::Verified { .. })
+    }
+
+    pub fn report(&self) -> () {
+        match self {
+            Verification::Verified { publisher } =>
+                println!("{} Signed by {}", "[signature]".green(), publisher.bold()),
+            Verification::Untrusted { publisher, public_key } =>
+                println!("{} Signed by {}, whose key ({}) is not trusted.", "[signature]".yellow(), publisher.bold(), public_key),
+            Verification::Unsigned =>
+                println!("{} Extension is not signed.", "[signature]".yellow()),
+            Verification::Tampered(reason) =>
+                println!("{} {}", "[signature]".bright_red(), reason.bright_red()),
+        }
+    }
+}
+
+fn payload(publisher: &str, files: &BTreeMap<String, String>) -> Vec<u8> {
+    let manifest = files.get(MANIFEST_FILE).map(|h| h.as_str()).unwrap_or("");
+
+    serde_json::to_vec(&Payload { publisher, manifest, files }).unwrap()
+}
+
+fn hashes(root: &Path) -> Result<BTreeMap<String, String>, String> {
+    let files = Package::files(root).map_err(|e| e.to_string())?;
+
+    Ok(files.iter().map(|(n, c)| (n.clone(), Package::hash(c))).collect())
+}
+
+///
+/// ## Keyring
+///
+/// Trusted publishers' public keys, in `~/.config/avdan-js/keyring.json`.
+///
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Keyring {
+    /// Publisher -> public key (hex).
+    keys : BTreeMap<String, String>,
+}
+
+impl Keyring {
+    fn file() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join(KEYRING_FILE))
+    }
+
+    pub fn load() -> Keyring {
+        Self::file()
+            .and_then(|f| fs::read(f).ok())
+            .and_then(|b| serde_json::from_slice(&b).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let file = Self::file().ok_or("No config directory!")?;
+
+        fs::create_dir_all(file.parent().unwrap()).map_err(|e| e.to_string())?;
+        fs::write(file, serde_json::to_vec_pretty(self).unwrap()).map_err(|e| e.to_string())
+    }
+
+    pub fn trust(&mut self, publisher: &str, public_key: &str) -> Result<(), String> {
+        let bytes = hex::decode(public_key).map_err(|e| e.to_string())?;
+        PublicKey::from_bytes(&bytes).map_err(|e| format!("Invalid public key: {}", e))?;
+
+        self.keys.insert(publisher.to_string(), public_key.to_lowercase());
+        Ok(())
+    }
+
+    pub fn publisher_of(&self, public_key: &str) -> Option<&String> {
+        self.keys.iter()
+            .find(|(_, k)| k.eq_ignore_ascii_case(public_key))
+            .map(|(p, _)| p)
+    }
+}
+
+impl ExtensionSignature {
+    /// Generates a new key pair, returning (secret key, public key) as hex.
+    pub fn keygen() -> (String, String) {
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+
+        let secret_key = SecretKey::from_bytes(&secret).unwrap();
+        let public_key = PublicKey::from(&secret_key);
+
+        (hex::encode(secret), hex::encode(public_key.as_bytes()))
+    }
+
+    /// Signs an extension's folder, writing its `signature.avdan.json`.
+    pub fn sign(dir: &Path, publisher: &str, secret_key: &str) -> Result<(), String> {
+        let secret = SecretKey::from_bytes(&hex::decode(secret_key.trim()).map_err(|e| e.to_string())?)
+            .map_err(|e| format!("Invalid secret key: {}", e))?;
+        let public = PublicKey::from(&secret);
+        let keypair = Keypair { secret, public };
+
+        let files = hashes(dir)?;
+
+        if !files.contains_key(MANIFEST_FILE) {
+            return Err(format!("`{}` does not have a `{}`!", dir.to_str().unwrap(), MANIFEST_FILE));
+        }
+
+        let signature = keypair.sign(&payload(publisher, &files));
+
+        let signed = ExtensionSignature {
+            publisher  : publisher.to_string(),
+            public_key : hex::encode(public.as_bytes()),
+            files,
+            signature  : hex::encode(signature.to_bytes()),
+        };
+
+        fs::write(dir.join(SIGNATURE_FILE), serde_json::to_vec_pretty(&signed).unwrap()).map_err(|e| e.to_string())
+    }
+
+    fn check(&self, root: &Path) -> Result<(), String> {
+        let public = PublicKey::from_bytes(&hex::decode(&self.public_key).map_err(|e| e.to_string())?)
+            .map_err(|e| format!("Invalid public key: {}", e))?;
+
+        let signature = hex::decode(&self.signature).map_err(|e| e.to_string())?;
+        let signature = Signature::try_from(signature.as_slice()).map_err(|e| format!("Invalid signature: {}", e))?;
+
+        public.verify(&payload(&self.publisher, &self.files), &signature)
+            .map_err(|_| "Signature does not match the signed files!".to_string())?;
+
+        // The signature holds, now make sure the files are still the signed ones.
+        let current = hashes(root)?;
+
+        for (name, hash) in current.iter() {
+            match self.files.get(name) {
+                None                 => return Err(format!("`{}` was added after signing!", name)),
+                Some(h) if h != hash => return Err(format!("`{}` was changed after signing!", name)),
+                Some(_)              => {}
+            }
+        }
+
+        if let Some(missing) = self.files.keys().find(|n| !current.contains_key(*n)) {
+            return Err(format!("`{}` was removed after signing!", missing));
+        }
+
+        Ok(())
+    }
+
+    /// Checks the signature of an extension (folder, or mounted package).
+    pub fn verify(root: &Path) -> Verification {
+        Self::verify_with(root, &Keyring::load())
+    }
+
+    /// Same as [`ExtensionSignature::verify`], trusting the keys in `keyring`.
+    pub fn verify_with(root: &Path, keyring: &Keyring) -> Verification {
+        let file = root.join(SIGNATURE_FILE);
+
+        if !Package::exists(&file) {
+            return Verification::Unsigned;
+        }
+
+        let signed : ExtensionSignature = match Package::read(&file).ok().and_then(|b| serde_json::from_slice(&b).ok()) {
+            Some(s) => s,
+            None    => return Verification::Tampered(format!("Could not parse `{}`!", SIGNATURE_FILE)),
+        };
+
+        if let Err(reason) = signed.check(root) {
+            return Verification::Tampered(reason);
+        }
+
+        match keyring.publisher_of(&signed.public_key) {
+            Some(publisher) => Verification::Verified { publisher: publisher.clone() },
+            None            => Verification::Untrusted { publisher: signed.publisher, public_key: signed.public_key },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::{ExtensionSignature, Keyring, Verification, SIGNATURE_FILE};
+    use crate::Avdan::{loader::{Package, MANIFEST_FILE}, runtime::avmod::testing::temp_dir};
+
+    /// A signed extension, and the public key it was signed with.
+    fn signed() -> (PathBuf, String) {
+        let dir = temp_dir(&[(MANIFEST_FILE, "{}"), ("Main.js", "export default 1;")]);
+        let (secret, public) = ExtensionSignature::keygen();
+
+        ExtensionSignature::sign(&dir, "avdan", &secret).unwrap();
+        (dir, public)
+    }
+
+    fn trusting(public_key: &str) -> Keyring {
+        let mut keyring = Keyring::default();
+        keyring.trust("avdan", public_key).unwrap();
+        keyring
+    }
+
+    fn tampered(verification: Verification) -> String {
+        match verification {
+            Verification::Tampered(reason) => reason,
+            _                              => panic!("Expected the extension to be tampered with!"),
+        }
+    }
+
+    #[test]
+    fn trusted_keys_verify() {
+        let (dir, public) = signed();
+
+        assert!(ExtensionSignature::verify_with(&dir, &trusting(&public)).is_verified());
+    }
+
+    #[test]
+    fn untrusted_keys_do_not_verify() {
+        let (dir, _) = signed();
+        let (_, other) = ExtensionSignature::keygen();
+
+        match ExtensionSignature::verify_with(&dir, &trusting(&other)) {
+            Verification::Untrusted { publisher, .. } => assert_eq!(publisher, "avdan"),
+            _                                         => panic!("Expected the key to be untrusted!"),
+        }
+    }
+
+    #[test]
+    fn modified_files_are_tampered() {
+        let (dir, public) = signed();
+        fs::write(dir.join("Main.js"), "export default 2;").unwrap();
+
+        let reason = tampered(ExtensionSignature::verify_with(&dir, &trusting(&public)));
+        assert!(reason.contains("`Main.js` was changed"), "{}", reason);
+    }
+
+    #[test]
+    fn added_and_removed_files_are_tampered() {
+        let (dir, public) = signed();
+        fs::write(dir.join(".extra.js"), "").unwrap();
+
+        let reason = tampered(ExtensionSignature::verify_with(&dir, &trusting(&public)));
+        assert!(reason.contains("`.extra.js` was added"), "{}", reason);
+
+        fs::remove_file(dir.join(".extra.js")).unwrap();
+        fs::remove_file(dir.join("Main.js")).unwrap();
+
+        let reason = tampered(ExtensionSignature::verify_with(&dir, &trusting(&public)));
+        assert!(reason.contains("`Main.js` was removed"), "{}", reason);
+    }
+
+    #[test]
+    fn rewritten_signatures_are_tampered() {
+        let (dir, public) = signed();
+        let file = dir.join(SIGNATURE_FILE);
+
+        // Re-hashing a modified file into the signature file doesn't make it match.
+        let mut signature : ExtensionSignature = serde_json::from_slice(&fs::read(&file).unwrap()).unwrap();
+        fs::write(dir.join("Main.js"), "export default 2;").unwrap();
+        signature.files.insert("Main.js".to_string(), Package::hash(b"export default 2;"));
+        fs::write(&file, serde_json::to_vec(&signature).unwrap()).unwrap();
+
+        let reason = tampered(ExtensionSignature::verify_with(&dir, &trusting(&public)));
+        assert!(reason.contains("Signature does not match"), "{}", reason);
+    }
+
+    #[test]
+    fn unsigned_extensions() {
+        let dir = temp_dir(&[(MANIFEST_FILE, "{}")]);
+
+        assert!(matches!(ExtensionSignature::verify_with(&dir, &Keyring::default()), Verification::Unsigned));
+    }
+}
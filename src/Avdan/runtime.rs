@@ -6,9 +6,7 @@ use std::cell::RefCell;
 use std::cell::UnsafeCell;
 use std::collections::HashMap;
 use std::env;
-use std::ffi::c_void;
 use std::fs;
-use std::intrinsics::transmute;
 use std::panic;
 use std::path::Path;
 use std::path::PathBuf;
@@ -16,6 +14,7 @@ use std::process::exit;
 use std::rc::Rc;
 use std::sync::mpsc::channel;
 use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
 use std::sync::mpsc::Sender;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -26,7 +25,6 @@ use v8;
 use v8::inspector::Channel;
 use v8::CallbackScope;
 use v8::Context;
-use v8::External;
 use v8::FixedArray;
 use v8::Global;
 use v8::HandleScope;
@@ -38,12 +36,18 @@ use v8::ScriptOrigin;
 use v8::TryCatch;
 use v8::Value;
 
+use crate::Avdan::runtime::avmod::AvMod;
 use crate::Avdan::runtime::avmod::AvModJS;
 use crate::Avdan::runtime::avmod::AvModProvider;
 use crate::Avdan::runtime::avmod::AvModStore;
+use crate::Avdan::runtime::avmod::AvModWasm;
+use crate::Avdan::runtime::avmod::Diagnostic;
+use crate::Avdan::runtime::avmod::DiagnosticKind;
+use crate::Avdan::runtime::avmod::Specifier;
 use crate::core::def_safe_property;
 use crate::core::JSApi;
-use crate::Avdan::loader::Extension;
+use crate::Avdan::loader::{Extension, Package};
+use crate::Avdan::security::{AuditLog, CodeGeneration, Grants, Secrets, quota_exception, QUOTA_EXCEEDED};
 
 use super::super::Avdan;
 
@@ -55,8 +59,23 @@ pub mod avmod;
 pub mod message;
 pub use message::{Builder, Message, Type};
 
-const TRANSMISSION_KEY: &str = "___TX___";
-const PROMISE_TABLE: &str = "___PROM___";
+pub mod source_map;
+pub use source_map::SourceMaps;
+
+pub mod watcher;
+pub use watcher::{Watcher, Changes};
+
+pub mod flags;
+pub use flags::Flags;
+
+pub mod lockdown;
+pub use lockdown::Lockdown;
+
+pub mod sandbox;
+pub use sandbox::{Sandbox, BrokerConsent, Request as SandboxRequest, Response as SandboxResponse};
+
+// How often the event loop checks for file changes in `--watch` mode.
+const WATCH_POLL: Duration = Duration::from_millis(100);
 
 pub type PromIndex = u32;
 type Prom = Global<PromiseResolver>;
@@ -64,6 +83,9 @@ type Prom = Global<PromiseResolver>;
 type TaskOut = Message;
 type PromTable = HashMap<PromIndex, Prom>;
 
+// In the isolate when running with `--watch`, where errors shouldn't end the process.
+struct Watching;
+
 pub struct Runtime<T> {
     tx: Option<Sender<T>>,
 }
@@ -78,13 +100,85 @@ impl Runtime<TaskOut> {
     }
 
     extern "C" fn promise_reject_callback<'a>(msg : PromiseRejectMessage<'a>) -> () {
+        // Only rejections nothing handles.
+        if msg.get_event() != v8::PromiseRejectEvent::PromiseRejectWithNoHandler {
+            return;
+        }
+
         let scope = &mut unsafe { v8::CallbackScope::new(&msg) };
         let v = msg.get_value().unwrap();
-        let s = v.to_rust_string_lossy(scope);
+        let s = SourceMaps::describe(scope, v);
         
         println!("\n{}\n{}", "Uncaught error in JS!".red(), s.bright_red());
         
-        exit(1);
+        // When watching, end this run (its tasks are forgotten) and wait for the next change.
+        match scope.get_slot::<Watching>() {
+            Some(_) => if let Some(table) = scope.get_slot_mut::<PromTable>() {
+                table.clear();
+            },
+            None    => exit(1),
+        }
+    }
+
+    /// Loads the extension again after any change -- every file is covered by the signature,
+    /// so a changed module can make a signed extension an unsigned one (with another identity,
+    /// and so other grants, secrets and policy rules).
+    /// While it can't be loaded (or isn't signed, with `--require-signed`), reports why and waits for another change.
+    fn reload_extension(path: &str, flags: &Flags, watcher: &mut Watcher) -> Extension {
+        loop {
+            match Extension::try_load(path, &flags.overrides) {
+                Ok(extension) => if Self::check_extension(&extension, flags) {
+                    // The parent answers for the extension (its identity...), so it has to load it again too.
+                    let synced = match Sandbox::is_child() {
+                        true  => Sandbox::request(&SandboxRequest::Reload).map(|_| ()),
+                        false => Ok(()),
+                    };
+
+                    match synced {
+                        Ok(_)    => {
+                            // Each run of the extension is recorded with the overrides it ran under.
+                            flags.overrides.audit(extension.name(), &extension.identity());
+                            return extension;
+                        },
+                        Err(err) => println!("\n{}\n{}", "Could not load the extension!".red(), err.bright_red()),
+                    }
+                },
+                Err(err) => {
+                    println!("\n{}\n{}", "Could not load the extension!".red(), err.bright_red());
+                },
+            }
+
+            watcher.wait().report();
+        }
+    }
+
+    /// Prints a module load failure, either for a person or,
+    /// with `--diagnostics=json`, as a single JSON line on stderr for a host.
+    fn report_diagnostic(diagnostic: &Diagnostic, json: bool) -> () {
+        match json {
+            true  => eprintln!("{}", diagnostic.to_json()),
+            false => println!("\n{}\n", diagnostic),
+        }
+    }
+
+    /// Reports the extension's signature (and what the policy removed), returning `false`
+    /// if `--require-signed` is on and it isn't signed by a trusted publisher.
+    fn check_extension(extension: &Extension, flags: &Flags) -> bool {
+        let verification = extension.verification();
+        verification.report();
+
+        flags.overrides.report();
+
+        for removed in extension.restricted() {
+            println!("{} Removed {}", "[policy]".yellow(), removed);
+        }
+
+        if flags.require_signed && !verification.is_verified() {
+            println!("{}", "Refusing to run an extension which is not signed by a trusted publisher (--require-signed).".bright_red());
+            return false;
+        }
+
+        true
     }
 
     pub fn run_extension(&mut self, args: Vec<String>) -> JoinHandle<()> {
@@ -97,12 +191,33 @@ impl Runtime<TaskOut> {
             panic!("Extension path not specified!");
         }
 
-        let experiental_module_flag = match args.get(2) {
-            Some(f) => f.eq("--module"),
-            None    => false,
-        };
+        Sandbox::connect();
+
+        let mut flags = Flags::parse(&args[2..]).unwrap_or_else(|err| panic!("{}", err));
 
-        let extension = Extension::from_manifest(args.get(1).clone().unwrap());
+        let extension_path = args.get(1).unwrap().clone();
+        let mut extension = Extension::load(&extension_path, &flags.overrides);
+
+        match Sandbox::is_child() {
+            // The parent has already checked it.
+            true  => {
+                Sandbox::restrict(Path::new(&extension_path), extension.security())
+                    .unwrap_or_else(|err| panic!("Could not sandbox the extension!\n{}", err));
+
+                flags.consent = Arc::new(BrokerConsent {});
+            },
+            false => {
+                if !Self::check_extension(&extension, &flags) {
+                    exit(1);
+                }
+
+                flags.overrides.audit(extension.name(), &extension.identity());
+
+                if flags.sandbox {
+                    exit(Sandbox::run(&args, &extension, &flags));
+                }
+            },
+        }
 
         // Async ???
 
@@ -111,7 +226,6 @@ impl Runtime<TaskOut> {
         self.tx = tx.clone().into();
 
         thread::spawn(move || {
-            let mut map: PromTable = HashMap::new();
             /*
              * V8 JavaScript (ECMAScript) Engine
              */
@@ -121,185 +235,295 @@ impl Runtime<TaskOut> {
             v8::V8::initialize_platform(platform);
             v8::V8::initialize();
 
-            {
-                // Create a new Isolate and make it the current one.
+            let mut watcher = match flags.watch {
+                true  => Some(Watcher::new(&extension_path).expect("Could not watch the extension's folder!")),
+                false => None,
+            };
+
+            let (mut tx, mut rx) = (tx, rx);
 
+            loop {
+                // Create a new Isolate and make it the current one.
                 let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
 
-                // Create a stack-allocated handle scope.
-                let handle_scope = &mut v8::HandleScope::new(isolate);
+                let changes = Self::run_main(isolate, &extension, &flags, tx, rx, watcher.as_mut());
+
+                let watcher = match watcher.as_mut() {
+                    Some(w) => w,
+                    None    => break,
+                };
+
+                let changes = changes.unwrap_or_else(|| watcher.wait());
+                changes.report();
+
+                (tx, rx) = channel();
+
+                // Whatever changed, the signature (and so the identity) is checked again.
+                extension = Self::reload_extension(&extension_path, &flags, watcher);
+            }
+
+            unsafe {
+                v8::V8::dispose();
+            }
+
+            v8::V8::dispose_platform();
+        })
+    }
 
-                // Create a new context.
-                let context = v8::Context::new(handle_scope);
+    /// Runs the extension's main script/module in a fresh context,
+    /// then its event loop until every task has finished.
+    ///
+    /// When watching, returns early with the changed files (if any).
+    fn run_main(
+        isolate      : &mut v8::OwnedIsolate,
+        extension    : &Extension,
+        flags        : &Flags,
+        tx           : Sender<TaskOut>,
+        rx           : Receiver<TaskOut>,
+        mut watcher  : Option<&mut Watcher>
+    ) -> Option<Changes> {
+        {
+            // Create a stack-allocated handle scope.
+            let handle_scope = &mut v8::HandleScope::new(isolate);
+
+            // Create a new context.
+            let context = v8::Context::new(handle_scope);
+
+            // Enter the context for script compilation and execution
+            let scope = &mut v8::ContextScope::new(handle_scope, context);
+
+            // Make a global scope thing-y
+            let global = context.global(scope);
 
-                // Enter the context for script compilation and execution
-                let scope = &mut v8::ContextScope::new(handle_scope, context);
+            /*
+             *     Security Policy
+             * 🚧 UNDER CONSTRUCTION 🚧
+             */
 
-                // Make a global scope thing-y
-                let global = context.global(scope);
+            SourceMaps::into_scope(scope);
 
-                /*
-                 *     Security Policy
-                 * 🚧 UNDER CONSTRUCTION 🚧
-                 */
+            // Apply security policy
+            extension.security().into_scope(scope);
+            Grants::load(&extension.identity()).into_scope(scope);
+            AuditLog::into_scope(extension.name(), &extension.identity(), scope);
+            Secrets::into_scope(&extension.identity(), scope);
+            scope.set_slot(flags.consent.clone());
+            scope.set_allow_wasm_code_generation_callback(AvModWasm::allow_wasm_code_generation_callback);
 
-                // Apply security policy
-                extension.security().into_scope(scope);
+            if !extension.security().allows_eval() {
+                CodeGeneration::disallow(scope);
+            }
 
-                let avdan_js = Avdan::api::AvdanAPI {}.js(scope);
+            let avdan_js = Avdan::api::AvdanAPI {}.js(scope);
 
-                def_safe_property(scope, global, "Avdan", avdan_js.into());
+            def_safe_property(scope, global, "Avdan", avdan_js.into());
 
-                // TODO: Move this
-                // {
-                // Put the async sender into the global JS scope.
-                let tx_ptr: *mut _ = &mut tx.clone();
-                let transmission = v8::External::new(scope, tx_ptr as *mut c_void);
-                
-                def_safe_property(scope, global, TRANSMISSION_KEY, transmission.into());
+            if flags.lockdown {
+                Lockdown::apply(scope).unwrap_or_else(|err| panic!("Could not lock down the context!\n{}", err));
+            }
 
-                // Put the promise map into the JS global scope.
-                let map_ptr: *mut _ = &mut map;
-                let prom_map = External::new(scope, map_ptr as *mut c_void);
+            // The tasks' sender and promise table are owned by the isolate,
+            // out of reach of JS (see `tx_from_scope` and `prom_map_insert`).
+            scope.set_slot(tx);
+            scope.set_slot(PromTable::new());
+
+            if watcher.is_some() {
+                scope.set_slot(Watching);
+            }
+            
+            scope.set_promise_reject_callback(Self::promise_reject_callback);
+
+            if flags.module {
+                let exp_warning_message = Colorize::yellow("Warning! --module is an experimental flag!\n");
                 
-                def_safe_property(scope, global, PROMISE_TABLE, prom_map.into());
+                println!("{}\n Do not expect anything to work !", exp_warning_message);
                 
-                scope.set_promise_reject_callback(Self::promise_reject_callback);
+                let scope = &mut v8::HandleScope::new(scope);
+                let try_catch = &mut TryCatch::new(scope);
+                
+                AvModStore::into_scope(try_catch);
+
+                let main_module = Package::canonicalize(Path::new(extension.main()))
+                    .map_err(|e| Diagnostic::new(DiagnosticKind::Io, e.to_string()).in_file(Path::new(extension.main())))
+                    .and_then(|path| {
+                        let (dir, file) = match (path.parent(), path.file_name()) {
+                            (Some(d), Some(f)) => (d.to_path_buf(), f.to_string_lossy().to_string()),
+                            _                  => return Err(Diagnostic::new(DiagnosticKind::Resolve, "Invalid main file!").in_file(&path)),
+                        };
 
-                if experiental_module_flag {
-                    let exp_warning_message = Colorize::yellow("Warning! --module is an experimental flag!\n");
-                    
-                    println!("{}\n Do not expect anything to work !", exp_warning_message);
-                    
-                    let scope = &mut v8::HandleScope::new(scope);
-                    let try_catch = &mut TryCatch::new(scope);
-                    
-                    AvModStore::into_scope(try_catch);
+                        let main_specifier : Specifier = format!("./{}", file).try_into()?;
 
-                    let main_module_path = Path::new(extension.main());
-                    
-                    let main_module = AvModJS::load_module(
-                        try_catch,
-                        &main_module_path.canonicalize().unwrap()
-                    );
-
-                    let main = match main_module {
-                        Ok(module) => module,
-                        Err(err)   => panic!("\n\n\t{}:\n\t\t{}\n\n", "Error".bright_red(), err)
-                    };
+                        AvMod::load(try_catch, &dir, main_specifier, None)
+                    });
 
-                    let m = main.open(try_catch);
-                    
-                    let a : Local<Promise> = m.evaluate(try_catch).unwrap().try_into().expect("Should be promise!");
+                let main = match main_module {
+                    Ok(module) => module,
+                    Err(diagnostic) => {
+                        Self::report_diagnostic(&diagnostic, flags.json_diagnostics);
 
-                    // let main_module = main_module.resolve(
-                    //     scope,
-                    //     env::current_dir().unwrap().to_str().unwrap().to_string(),
-                    // ).unwrap();
+                        // Keep watching, the next save might fix it.
+                        match watcher {
+                            Some(_) => return None,
+                            None    => exit(1),
+                        }
+                    }
+                };
 
-                    // let main = main_module
-                    //     .open(scope);
-                    
-                    // main.evaluate(scope).unwrap();
+                let m = main.open(try_catch);
 
-                    // Check if there was an error in the javascript
-                    // Run the script to get the result.
-                } else {
-                    // Compile the source code.
-                    let source_code = fs::read_to_string(extension.main()).unwrap();
-                    let source_code = v8::String::new(scope, &source_code).unwrap();
-                    let script = v8::Script::compile(scope, source_code, None);
+                // A rejected promise is reported by `promise_reject_callback`.
+                if m.evaluate(try_catch).is_none() {
+                    let failure = try_catch.get_slot_mut::<AvModStore>().and_then(|s| s.take_failure());
 
-                    script.expect("Error in the script!").run(scope).unwrap();
+                    match (failure, try_catch.exception()) {
+                        (Some(d), _)    => Self::report_diagnostic(&d, flags.json_diagnostics),
+                        (None, Some(e)) => println!("\n{}\n{}", "Uncaught error in JS!".red(), SourceMaps::describe(try_catch, e).bright_red()),
+                        (None, None)    => println!("\n{}", "Could not evaluate the main module!".red()),
+                    }
+
+                    match watcher {
+                        Some(_) => return None,
+                        None    => exit(1),
+                    }
                 }
 
-                if map.len() != 0 {
-                    for msg in rx {
-                        // Very simplified event loop.
-
-                        let id = msg.0;
-
-                        let p = map.get(&id).expect("Should have got promise !");
-                        let prom = p.open(scope);
-
-                        match msg.1 {
-                            Type::Auxiliary(k, contents, fn_ptr) => {
-                                match Task::get_auxiliary_func(scope, prom, k) {
-                                    Some(f) => {
-                                        let obj = fn_ptr(scope, contents);
-                                        
-                                        let local = unsafe {
-                                            transmute::<&PromiseResolver, Local<PromiseResolver>>(
-                                                prom,
-                                            )
-                                        };
-                                        
-                                        f.call(scope, local.into(), &[obj]);
-                                    }
-                                    
-                                    None => {}
-                                }
-                            }
-                            
-                            Type::Result(contents, builder) => {
-                                // Get Promise, and resolve it, then remove from the table.
-                                match contents {
-                                    Err(txt) => {
-                                        let e = v8::String::new(scope, &txt).unwrap();
-                                        let err = v8::Exception::error(scope, e);
-                                        
-                                        prom.reject(scope, err.into());
-                                    }
-                                    Ok(result) => {
-                                        let r_value = builder(scope, result);
-                                        
-                                        prom.resolve(scope, r_value);
-                                    }
-                                }
-                                
-                                map.remove(&id);
-                            }
-                        };
+                // let main_module = main_module.resolve(
+                //     scope,
+                //     env::current_dir().unwrap().to_str().unwrap().to_string(),
+                // ).unwrap();
 
-                        if map.len() == 0 {
-                            break;
+                // let main = main_module
+                //     .open(scope);
+                
+                // main.evaluate(scope).unwrap();
+
+                // Check if there was an error in the javascript
+                // Run the script to get the result.
+            } else {
+                // Compile the source code.
+                let main_path = PathBuf::from(extension.main());
+
+                let source_code = match Package::read(&main_path).ok().and_then(|b| String::from_utf8(b).ok()) {
+                    Some(s) => s,
+                    None    => {
+                        println!("\n{} `{}`", "Could not read the extension's main file".red(), extension.main());
+
+                        match watcher {
+                            Some(_) => return None,
+                            None    => exit(1),
                         }
                     }
+                };
+
+                let source_map_url = SourceMaps::register(scope, extension.main(), &main_path, &source_code);
+
+                let name = v8::String::new(scope, extension.main()).unwrap();
+                let source_map_url = v8::String::new(scope, &source_map_url).unwrap();
+                let origin = ScriptOrigin::new(
+                    scope, name.into(), 0, 0, false, 0, source_map_url.into(), false, false, false
+                );
+
+                let source_code = v8::String::new(scope, &source_code).unwrap();
+
+                let try_catch = &mut TryCatch::new(scope);
+                let ran = v8::Script::compile(try_catch, source_code, Some(&origin))
+                    .and_then(|script| script.run(try_catch));
+
+                if ran.is_none() {
+                    let err = match try_catch.exception() {
+                        Some(e) => SourceMaps::describe(try_catch, e),
+                        None    => "Error in the script!".to_string(),
+                    };
+
+                    println!("\n{}\n{}", "Uncaught error in JS!".red(), err.bright_red());
+
+                    match watcher {
+                        Some(_) => return None,
+                        None    => exit(1),
+                    }
                 }
             }
 
-            unsafe {
-                v8::V8::dispose();
+            while scope.get_slot::<PromTable>().map(|t| !t.is_empty()).unwrap_or(false) {
+                // Very simplified event loop.
+                let msg = match watcher.as_mut() {
+                    None    => match rx.recv() {
+                        Ok(msg) => msg,
+                        Err(_)  => break,
+                    },
+
+                    // Keep an eye on the extension's files whilst tasks are running.
+                    Some(w) => match rx.recv_timeout(WATCH_POLL) {
+                        Ok(msg)                          => msg,
+                        Err(RecvTimeoutError::Timeout)   => match w.poll() {
+                            Some(changes) => return Some(changes),
+                            None          => continue,
+                        },
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    },
+                };
+
+                let id = msg.0;
+
+                // A task of a run which has ended (see `promise_reject_callback`).
+                let p = match scope.get_slot::<PromTable>().unwrap().get(&id).cloned() {
+                    Some(p) => p,
+                    None    => continue,
+                };
+                let prom = Local::new(scope, p);
+
+                match msg.1 {
+                    Type::Auxiliary(k, contents, fn_ptr) => {
+                        match Task::get_auxiliary_func(scope, prom, k) {
+                            Some(f) => {
+                                let obj = fn_ptr(scope, contents);
+                                
+                                f.call(scope, prom.into(), &[obj]);
+                            }
+                            
+                            None => {}
+                        }
+                    }
+                    
+                    Type::Result(contents, builder) => {
+                        // Get Promise, and resolve it, then remove from the table.
+                        match contents {
+                            Err(txt) if txt.starts_with(QUOTA_EXCEEDED) => {
+                                let err = quota_exception(scope, &txt);
+                                
+                                prom.reject(scope, err);
+                            }
+                            Err(txt) => {
+                                let e = v8::String::new(scope, &txt).unwrap();
+                                let err = v8::Exception::error(scope, e);
+                                
+                                prom.reject(scope, err.into());
+                            }
+                            Ok(result) => {
+                                let r_value = builder(scope, result);
+                                
+                                prom.resolve(scope, r_value);
+                            }
+                        }
+                        
+                        scope.get_slot_mut::<PromTable>().unwrap().remove(&id);
+                    }
+                };
             }
+        }
 
-            v8::V8::dispose_platform();
-        })
+        None
     }
 
     pub fn tx_from_scope<'a>(scope: &mut HandleScope<'a>) -> Sender<TaskOut> {
-        let key = v8::String::new(scope, TRANSMISSION_KEY).unwrap();
-        let global = scope.get_current_context().global(scope);
-
-        let __tx: Result<Local<External>, _> = global.get(scope, key.into()).unwrap().try_into();
-        let _tx = __tx.expect("Cannot cast tx into v8::External !");
-
-        let tx = _tx.value() as *mut Sender<TaskOut>;
-
-        unsafe { tx.as_mut() }
-            .expect("Cannot change as mut!")
-            .to_owned()
+        scope.get_slot::<Sender<TaskOut>>()
+            .expect("The runtime's sender should be in the isolate!")
+            .clone()
     }
 
     pub fn prom_map_insert<'a>(scope: &mut HandleScope<'a>, prom: Prom) -> PromIndex {
-        let key = v8::String::new(scope, PROMISE_TABLE).unwrap();
-        let global = scope.get_current_context().global(scope);
-
-        let ___tbl: Result<Local<External>, _> = global.get(scope, key.into()).unwrap().try_into();
-        let __tbl = ___tbl.expect("Cannot cast prom_tbl into v8::External !");
-        let _tbl = __tbl.value() as *mut HashMap<PromIndex, Prom>;
-
-        let tbl = unsafe { _tbl.as_mut() }
-            .expect("Cannot change to mut !");
+        let tbl = scope.get_slot_mut::<PromTable>()
+            .expect("The promise table should be in the isolate!");
 
         let mut rng = rand::thread_rng();
         let mut i = 0u32;
@@ -1,41 +1,131 @@
-use std::{path::PathBuf, fs};
+use std::path::PathBuf;
 use colored::Colorize;
-use v8::{Global, Module, TryCatch, HandleScope, Context, Local, Value, CallbackScope, PromiseResolver};
+use v8::{Global, Module, TryCatch, HandleScope, Context, Local, Value, CallbackScope, PromiseResolver, ModuleRequest};
+
+use crate::Avdan::{utils, loader::Package};
 
 mod json;
 mod js;
+mod ts;
+mod cjs;
+mod text;
+mod bytes;
+mod wasm;
+mod cache;
+mod diagnostic;
 mod resource;
 mod store;
 mod internal;
+#[cfg(test)]
+pub(crate) mod testing;
 pub use internal::AvModInternal;
-pub(crate) use store::AvModStore;
+pub(crate) use store::{AvModStore, ModuleKey};
 pub use js::AvModJS;
+pub use ts::AvModTS;
+pub use cjs::AvModCJS;
+pub use text::AvModText;
+pub use bytes::AvModBytes;
+pub use wasm::AvModWasm;
+pub use cache::CodeCache;
+pub use diagnostic::{Diagnostic, DiagnosticKind};
 pub use json::AvModJSON;
 pub use resource::{Specifier, SourceFile};
 
 pub struct AvMod {}
 
 impl AvMod {
-    pub fn load_file(path: &PathBuf) -> String {
-        fs::read_to_string(path).unwrap()
+    pub fn load_file(path: &PathBuf) -> Result<String, Diagnostic> {
+        let bytes = Package::read(path)
+            .map_err(|e| Diagnostic::new(DiagnosticKind::Io, e.to_string()).in_file(path))?;
+
+        String::from_utf8(bytes)
+            .map_err(|_| Diagnostic::new(DiagnosticKind::Io, "File is not valid UTF-8!").in_file(path))
+    }
+
+    /// Reads the `type` import assertion of a module request, if any:
+    /// ```js
+    /// import tpl from "./row.html" assert { type: "text" };
+    /// ```
+    pub fn import_type<'a>(scope: &mut HandleScope<'a>, request: Local<ModuleRequest>) -> Option<String> {
+        // Assertions come as a flat list of [key, value, source offset] triples.
+        Self::assertion_type(scope, request.get_import_assertions(), 3)
+    }
+
+    /// Like [`AvMod::import_type`], from the assertions V8 passes a resolve callback --
+    /// [key, value] pairs, without source offsets.
+    pub fn resolved_import_type<'a>(scope: &mut HandleScope<'a>, assertions: Local<v8::FixedArray>) -> Option<String> {
+        Self::assertion_type(scope, assertions, 2)
     }
 
-    pub fn load_from_file<'a>(scope: &mut HandleScope<'a>, dir: &PathBuf, file: SourceFile) -> Result<Global<Module>, String> {
-        let p = file.to_path(dir.to_str().unwrap().to_string())?;
+    fn assertion_type<'a>(scope: &mut HandleScope<'a>, assertions: Local<v8::FixedArray>, stride: usize) -> Option<String> {
+        let assertions = utils::fixed_array_to_vec::<Value>(scope, assertions);
+
+        assertions.chunks(stride)
+            .find(|a| a.len() >= 2 && a[0].to_rust_string_lossy(scope) == "type")
+            .map(|a| a[1].to_rust_string_lossy(scope))
+    }
+
+    pub fn load_from_file<'a>(scope: &mut HandleScope<'a>, dir: &PathBuf, file: SourceFile, import_type: Option<String>) -> Result<Global<Module>, Diagnostic> {
+        let p = file.to_path(dir.to_str().unwrap().to_string())
+            .map_err(|e| Diagnostic::new(DiagnosticKind::Io, e))?;
+
+        match import_type.as_deref() {
+            Some("text")  => return AvModText::load_module(&mut TryCatch::new(scope), &p),
+            Some("bytes") => return AvModBytes::load_module(&mut TryCatch::new(scope), &p),
+            Some("json")  => return AvModJSON::load_module(&mut TryCatch::new(scope), &p),
+            Some(t)       => return Err(Diagnostic::new(DiagnosticKind::Unsupported, format!("Unrecognised import type {}", t)).in_file(&p)),
+            None          => {}
+        }
         
-        match file {
+        // Dispatch on the resolved file, `./dependency` might have become `./dependency.js`.
+        match SourceFile::new(p.to_str().unwrap().to_string()) {
+            _ if AvModCJS::is_commonjs(&p) => AvModCJS::load_module(&mut TryCatch::new(scope), &p),
             f if f.is_js_file()          => AvModJS::load_module(&mut TryCatch::new(scope), &p),
+            f if f.is_ts_file()          => AvModTS::load_module(&mut TryCatch::new(scope), &p),
             f if f.extension() == "json" => AvModJSON::load_module(&mut TryCatch::new(scope), &p),
-            f => Err(format!("Unrecognised file format {}", f.extension()))
+            f if f.extension() == "wasm" => AvModWasm::load_module(&mut TryCatch::new(scope), &p),
+            f => Err(Diagnostic::new(DiagnosticKind::Unsupported, format!("Unrecognised file format `{}`", f.extension())).in_file(&p))
         }
     }
 
-    pub fn load<'a>(scope: &mut HandleScope<'a>, dir: &PathBuf, resource: Specifier) -> Result<Global<Module>, String> {
-        match resource {
-            Specifier::File(f)      => Self::load_from_file(scope, dir, f),
-            Specifier::Module(id)   => todo!(),
-            Specifier::Internal(id) => AvModInternal::get_internal_module(scope, id)
+    /// Loads a module -- or gets it from the store, if the same file was already imported the same way.
+    pub fn load<'a>(scope: &mut HandleScope<'a>, dir: &PathBuf, resource: Specifier, import_type: Option<String>) -> Result<Global<Module>, Diagnostic> {
+        let key = match &resource {
+            Specifier::File(f)      => f.to_path(dir.to_str().unwrap().to_string())
+                .map(|p| ModuleKey::file(&p, import_type.clone()))
+                .map_err(|e| Diagnostic::new(DiagnosticKind::Io, e))?,
+            Specifier::Internal(id) => ModuleKey::internal(id),
+            Specifier::Module(id)   => return Err(Diagnostic::new(DiagnosticKind::Unsupported, format!("External module `{}` not supported yet!", id))),
+        };
+
+        if let Some(module) = scope.get_slot::<AvModStore>().and_then(|s| s.get(&key)).cloned() {
+            return Ok(module);
+        }
+
+        let module = match resource {
+            Specifier::File(f)      => Self::load_from_file(scope, dir, f, import_type)?,
+            Specifier::Internal(id) => AvModInternal::get_internal_module(scope, id)?,
+            Specifier::Module(_)    => unreachable!(),
+        };
+
+        if let Some(store) = scope.get_slot_mut::<AvModStore>() {
+            store.register(key, module.clone());
         }
+
+        Ok(module)
+    }
+
+    /// Fails a V8 callback (module linking or evaluation) -- the diagnostic is kept in the store
+    /// for the loader to report, and thrown as an `Error` so V8 stops there.
+    pub(crate) fn fail<'a>(scope: &mut HandleScope<'a>, diagnostic: Diagnostic) -> () {
+        let message = v8::String::new(scope, &diagnostic.message).unwrap();
+        let exception = v8::Exception::error(scope, message);
+
+        if let Some(store) = scope.get_slot_mut::<AvModStore>() {
+            store.fail(diagnostic);
+        }
+
+        scope.throw_exception(exception);
     }
 
     fn instantiate_callback<'a> (
@@ -63,8 +153,8 @@ impl AvMod {
 
         match r {
             None    => {
-                let excep = scope.exception().unwrap();
-                panic!("{}", format!("Error from JS:\n\t{}", excep.to_rust_string_lossy(scope).as_str().bright_red()));
+                scope.rethrow();
+                return None;
             },
             Some(_) => {},
         }
@@ -88,7 +178,7 @@ pub trait AvModProvider {
     fn load_module<'a>(
         scope: &mut TryCatch<HandleScope<'a>>,
         path : &PathBuf
-    ) -> Result<Global<Module>, String>;
+    ) -> Result<Global<Module>, Diagnostic>;
 
     fn _instantiate_callback<'a> (
         context           : v8::Local<'a, v8::Context>,
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use v8::{Global, Module, HandleScope, TryCatch};
+
+use crate::Avdan::{runtime::output, loader::Package};
+
+use super::{AvModProvider, AvModText, Diagnostic, DiagnosticKind};
+
+///
+/// ## AvModBytes
+///
+/// `import icon from "./icon.png" assert { type: "bytes" };`
+///
+/// Exposes the raw contents of the file as a `Uint8Array` (default export).
+///
+
+pub struct AvModBytes {}
+
+impl AvModProvider for AvModBytes {
+    fn load_module<'a> (
+        scope : &mut TryCatch<HandleScope<'a>>,
+        path  : &PathBuf
+    ) -> Result<Global<Module>, Diagnostic> {
+        let contents = Package::read(path)
+            .map_err(|e| Diagnostic::new(DiagnosticKind::Io, e.to_string()).in_file(path))?;
+        let bytes = output::uint8_array(scope, contents);
+
+        AvModText::synthetic_module(scope, path, bytes)
+    }
+
+    fn _instantiate_callback<'a> (
+        context           : v8::Local<'a, v8::Context>,
+        specifier         : v8::Local<'a, v8::String>,
+        import_assertions : v8::Local<'a, v8::FixedArray>,
+        referrer          : v8::Local<'a, v8::Module>,
+    ) -> Option<v8::Local<'a, v8::Module>> {
+        Some(referrer)
+    }
+}
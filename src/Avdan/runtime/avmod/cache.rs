@@ -0,0 +1,73 @@
+use std::{path::PathBuf, fs};
+
+use sha2::{Digest, Sha256};
+
+const CACHE_DIR : &str = "avdan-js/code";
+
+///
+/// ## CodeCache
+///
+/// On-disk V8 code cache for compiled modules (and serialized WebAssembly).
+///
+/// Entries are keyed by the module's canonical path, the hash of its source,
+/// and the V8 version, so any change to one of them simply misses the cache.
+/// WebAssembly is keyed by its content alone, so extensions shipping the same `.wasm` share it.
+///
+
+pub struct CodeCache {
+    file : Option<PathBuf>,
+}
+
+impl CodeCache {
+    pub fn new(path: &PathBuf, source: &str) -> CodeCache {
+        let canonical = path.canonicalize().unwrap_or(path.clone());
+
+        let mut hasher = Sha256::new();
+
+        hasher.update(canonical.to_string_lossy().as_bytes());
+        hasher.update([0u8]);
+        hasher.update(Sha256::digest(source.as_bytes()));
+        hasher.update([0u8]);
+        hasher.update(v8::V8::get_version().as_bytes());
+
+        Self::from_key(hasher, "bin")
+    }
+
+    pub fn for_wasm(bytes: &[u8]) -> CodeCache {
+        let mut hasher = Sha256::new();
+
+        hasher.update(Sha256::digest(bytes));
+        hasher.update([0u8]);
+        hasher.update(v8::V8::get_version().as_bytes());
+
+        Self::from_key(hasher, "wasm")
+    }
+
+    fn from_key(hasher: Sha256, extension: &str) -> CodeCache {
+        let key = hex::encode(hasher.finalize());
+
+        CodeCache {
+            file : dirs::cache_dir().map(|d| d.join(CACHE_DIR).join(format!("{}.{}", key, extension)))
+        }
+    }
+
+    /// The cached blob, if there is one.
+    pub fn load(&self) -> Option<Vec<u8>> {
+        fs::read(self.file.as_ref()?).ok()
+    }
+
+    pub fn store(&self, data: &[u8]) -> () {
+        // A failure to cache shouldn't stop the module from loading.
+        if let Some(f) = &self.file {
+            let _ = fs::create_dir_all(f.parent().unwrap())
+                .and_then(|_| fs::write(f, data));
+        }
+    }
+
+    /// Removes a blob V8 has rejected (e.g. different V8 flags).
+    pub fn invalidate(&self) -> () {
+        if let Some(f) = &self.file {
+            let _ = fs::remove_file(f);
+        }
+    }
+}
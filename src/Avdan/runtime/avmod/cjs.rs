@@ -0,0 +1,398 @@
+use std::path::{PathBuf, Path};
+
+use v8::{TryCatch, HandleScope, Global, Module, Local, Context, Value, CallbackScope, Object, Function, ScriptOrigin};
+
+use crate::{core::obj_get_property, Avdan::{runtime::SourceMaps, loader::Package}};
+
+use super::{AvModProvider, AvMod, AvModStore, AvModInternal, Specifier, Diagnostic, DiagnosticKind};
+
+const PACKAGE_FILE : &str = "package.json";
+
+// Keep the wrapper on the same line as the module's first line,
+// so line numbers in stack traces still match the original file.
+const WRAPPER_HEAD : &str = "(function (exports, require, module, __filename, __dirname) { ";
+const WRAPPER_TAIL : &str = "\n})";
+
+///
+/// ## AvModCJS
+///
+/// Loads CommonJS modules -- `.cjs` files, or `.js` files
+/// whose nearest `package.json` says `"type": "commonjs"`.
+///
+/// The code is wrapped in a module function (`exports`, `require`, `module`, `__filename`, `__dirname`),
+/// and `module.exports` is exposed as the default export to ES module importers.
+///
+
+pub struct AvModCJS {}
+
+impl AvModCJS {
+    fn package_type(dir: &Path) -> Option<String> {
+        for d in dir.ancestors() {
+            let package = d.join(PACKAGE_FILE);
+
+            if !Package::exists(&package) {
+                continue;
+            }
+
+            let contents = Package::read(&package).ok()?;
+            let json : serde_json::Value = serde_json::from_slice(&contents).ok()?;
+
+            return json.get("type")
+                .and_then(|t| t.as_str())
+                .map(|t| t.to_string());
+        }
+
+        None
+    }
+
+    /// Should this (existing) file be loaded as CommonJS?
+    pub fn is_commonjs(path: &PathBuf) -> bool {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("cjs") => true,
+            Some("js")  => Self::package_type(path.parent().unwrap()).as_deref() == Some("commonjs"),
+            _           => false,
+        }
+    }
+
+    /// Throws an `Error` -- for failures which aren't already a JS exception.
+    fn throw<'a, T>(scope: &mut HandleScope<'a>, message: &str) -> Option<T> {
+        let msg = v8::String::new(scope, message).unwrap();
+        let excp = v8::Exception::error(scope, msg);
+
+        scope.throw_exception(excp);
+        None
+    }
+
+    /// Compiles a module's code into its module function.
+    /// A `SyntaxError` is left pending as V8 threw it (with its location).
+    fn compile_wrapper<'a>(scope: &mut HandleScope<'a>, path: &PathBuf) -> Option<Local<'a, Function>> {
+        let code = match AvMod::load_file(path) {
+            Ok(c)  => c,
+            Err(d) => return Self::throw(scope, &d.to_string()),
+        };
+
+        let wrapped = format!("{}{}{}", WRAPPER_HEAD, code, WRAPPER_TAIL);
+        let source  = v8::String::new(scope, wrapped.as_str()).unwrap();
+
+        let source_map_url = SourceMaps::register(scope, path.to_str().unwrap(), path, wrapped.as_str());
+
+        let name = v8::String::new(scope, path.to_str().unwrap()).unwrap();
+        let source_map_url = v8::String::new(scope, source_map_url.as_str()).unwrap();
+        let origin = ScriptOrigin::new (
+            scope,
+            name.into(),
+            0i32,
+            0i32,
+            false,
+            0i32,
+            source_map_url.into(),
+            false,
+            false,
+            false
+        );
+
+        let func = v8::Script::compile(scope, source, Some(&origin))
+            .and_then(|script| script.run(scope))?;
+
+        match Local::<Function>::try_from(func) {
+            Ok(f)  => Some(f),
+            Err(_) => Self::throw(scope, &format!("Could not compile CommonJS module `{}`!", path.to_string_lossy())),
+        }
+    }
+
+    /// Creates a `require` function bound to the directory of the module using it.
+    fn require_function<'a>(scope: &mut HandleScope<'a>, dir: &Path) -> Local<'a, Function> {
+        let dir = v8::String::new(scope, dir.to_str().unwrap()).unwrap();
+
+        v8::Function::builder(Self::require_callback)
+            .data(dir.into())
+            .build(scope)
+            .unwrap()
+    }
+
+    /// Runs a CommonJS module (once), returning its `module.exports`.
+    /// If it fails, the exception is left pending -- whatever the module threw, as it was thrown.
+    pub fn require_path<'a>(scope: &mut HandleScope<'a>, path: &PathBuf) -> Option<Local<'a, Value>> {
+        let result = Self::run_module(scope, path);
+
+        // A half-initialized module mustn't be handed out later.
+        if result.is_none() {
+            scope.get_slot_mut::<AvModStore>().unwrap().remove_cjs_module(path);
+        }
+
+        result
+    }
+
+    fn run_module<'a>(scope: &mut HandleScope<'a>, path: &PathBuf) -> Option<Local<'a, Value>> {
+        let cached = scope.get_slot::<AvModStore>().unwrap()
+            .get_cjs_module(path)
+            .cloned();
+
+        let module = match cached {
+            Some(module) => {
+                let module = Local::new(scope, module);
+                return Some(obj_get_property(scope, module, "exports"));
+            }
+            None => Object::new(scope),
+        };
+
+        let exports = Object::new(scope);
+        let exports_key = v8::String::new(scope, "exports").unwrap();
+        module.set(scope, exports_key.into(), exports.into());
+
+        // Register before running, so circular `require`s see the partial exports.
+        {
+            let g = Global::new(scope, module);
+            let store = scope.get_slot_mut::<AvModStore>().unwrap();
+            store.add_cjs_module(path.clone(), g);
+        }
+
+        let dir = path.parent().unwrap();
+        let wrapper = Self::compile_wrapper(scope, path)?;
+
+        let require  = Self::require_function(scope, dir);
+        let filename = v8::String::new(scope, path.to_str().unwrap()).unwrap();
+        let dirname  = v8::String::new(scope, dir.to_str().unwrap()).unwrap();
+
+        let recv = v8::undefined(scope);
+
+        wrapper.call(scope, recv.into(), &[
+            exports.into(), require.into(), module.into(), filename.into(), dirname.into()
+        ])?;
+
+        Some(obj_get_property(scope, module, "exports"))
+    }
+
+    /// `require(specifier)`
+    ///
+    /// Resolves through the same [`Specifier`] rules as ES module imports.
+    pub fn require_callback (
+        scope  : &mut v8::HandleScope,
+        args   : v8::FunctionCallbackArguments,
+        mut rv : v8::ReturnValue
+    ) -> () {
+        if !args.get(0).is_string() {
+            let msg = v8::String::new(scope, "require's specifier parameter not provided (or not a string).").unwrap();
+            let excp = v8::Exception::type_error(scope, msg);
+
+            scope.throw_exception(excp);
+            return;
+        }
+
+        let dir = PathBuf::from(args.data().unwrap().to_rust_string_lossy(scope));
+        let specifier = args.get(0).to_rust_string_lossy(scope);
+
+        // Otherwise, the exception is pending.
+        if let Some(v) = Self::require(scope, &dir, specifier) {
+            rv.set(v);
+        }
+    }
+
+    fn require<'a>(scope: &mut HandleScope<'a>, dir: &PathBuf, specifier: String) -> Option<Local<'a, Value>> {
+        let res : Specifier = match specifier.clone().try_into() {
+            Ok(s)    => s,
+            Err(err) => return Self::throw(scope, &err),
+        };
+
+        match res {
+            Specifier::Internal(name) => match AvModInternal::api_object(scope, name) {
+                Ok(v)    => Some(v),
+                Err(err) => Self::throw(scope, &err),
+            },
+
+            Specifier::File(f) => {
+                let p = match f.to_path(dir.to_str().unwrap().to_string()) {
+                    Ok(p)    => p,
+                    Err(err) => return Self::throw(scope, &err),
+                };
+
+                match f.extension() {
+                    _ if Self::is_commonjs(&p) => Self::require_path(scope, &p),
+                    // A `SyntaxError` from `JSON.parse` is left pending.
+                    e if e == "json" => {
+                        let contents = match AvMod::load_file(&p) {
+                            Ok(c)  => c,
+                            Err(d) => return Self::throw(scope, &d.to_string()),
+                        };
+
+                        let contents = v8::String::new(scope, contents.as_str()).unwrap();
+                        v8::json::parse(scope, contents)
+                    },
+                    _ => Self::throw(scope, &format!("Cannot require() ES module `{}`, use `import` instead!", specifier)),
+                }
+            },
+
+            Specifier::Module(_) => Self::throw(scope, &format!("External module `{}` not supported yet!", specifier)),
+        }
+    }
+
+    fn evaluation_steps<'a> (
+        ctx   : Local<'a, Context>,
+        module: Local<'a, Module>
+    ) -> Option<Local<'a, Value>> {
+        let scope = &mut unsafe {
+            CallbackScope::new(ctx)
+        };
+
+        let g = Global::new(scope, module);
+        let path = match scope.get_slot::<AvModStore>().and_then(|s| s.get_cjs(&g)) {
+            Some(p)   => p,
+            None      => {
+                AvMod::fail(scope, Diagnostic::new(DiagnosticKind::Instantiate, "CommonJS module has no path!"));
+                return None;
+            }
+        };
+
+        let s = &mut unsafe {
+            CallbackScope::new(ctx)
+        };
+
+        let try_catch = &mut TryCatch::new(s);
+
+        // Whatever the module threw goes on to the importer, as it was thrown.
+        let exports = match Self::require_path(try_catch, &path) {
+            Some(exports) => exports,
+            None          => {
+                try_catch.rethrow();
+                return None;
+            }
+        };
+
+        let default = v8::String::new(try_catch, "default").unwrap();
+
+        match module.set_synthetic_module_export (
+            try_catch,
+            default,
+            exports
+        ) {
+            Some(_) => {},
+            None    => {
+                try_catch.rethrow();
+                return None;
+            }
+        }
+
+        Some(v8::undefined(try_catch).into())
+    }
+}
+
+impl AvModProvider for AvModCJS {
+    fn load_module<'a> (
+        scope : &mut TryCatch<HandleScope<'a>>,
+        path  : &PathBuf
+    ) -> Result<Global<Module>, Diagnostic> {
+        let export_names = [v8::String::new(scope, "default").unwrap()];
+
+        let name = v8::String::new(scope, path.to_str().unwrap()).unwrap();
+        let module = v8::Module::create_synthetic_module(scope, name, &export_names, Self::evaluation_steps);
+
+        {
+            let g      = Global::new(scope, module);
+            let store  = scope.get_slot_mut::<AvModStore>().unwrap();
+
+            store.add_cjs(g, path.clone());
+        }
+
+        module.instantiate_module(scope, Self::_instantiate_callback);
+
+        Ok(Global::new(scope, module))
+    }
+
+    fn _instantiate_callback<'a> (
+        context           : v8::Local<'a, v8::Context>,
+        specifier         : v8::Local<'a, v8::String>,
+        import_assertions : v8::Local<'a, v8::FixedArray>,
+        referrer          : v8::Local<'a, v8::Module>,
+    ) -> Option<v8::Local<'a, v8::Module>> {
+        Some(referrer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AvModCJS;
+    use crate::Avdan::runtime::avmod::testing::{temp_dir, with_scope};
+
+    #[test]
+    fn commonjs_detection() {
+        let dir = temp_dir(&[
+            ("a.cjs", ""),
+            ("a.mjs", ""),
+            ("a.js", ""),
+            ("cjs/package.json", r#"{ "type": "commonjs" }"#),
+            ("cjs/b.js", ""),
+            ("cjs/esm/package.json", r#"{ "type": "module" }"#),
+            ("cjs/esm/c.js", ""),
+            ("cjs/deep/d.js", ""),
+        ]);
+
+        assert!(AvModCJS::is_commonjs(&dir.join("a.cjs")));
+        assert!(!AvModCJS::is_commonjs(&dir.join("a.mjs")));
+        assert!(!AvModCJS::is_commonjs(&dir.join("a.js")));
+        assert!(AvModCJS::is_commonjs(&dir.join("cjs/b.js")));
+        assert!(AvModCJS::is_commonjs(&dir.join("cjs/deep/d.js")));
+        // The closest package.json wins.
+        assert!(!AvModCJS::is_commonjs(&dir.join("cjs/esm/c.js")));
+    }
+
+    #[test]
+    fn require_of_an_ungranted_internal_module_throws() {
+        let dir = temp_dir(&[("main.cjs", "module.exports = require(\"@avdan/shell\");")]);
+
+        with_scope(vec![], |scope| {
+            let scope = &mut v8::TryCatch::new(scope);
+
+            assert!(AvModCJS::require_path(scope, &dir.join("main.cjs")).is_none());
+
+            let err = scope.exception().unwrap().to_rust_string_lossy(scope);
+            assert!(err.contains("SecurityException"), "{}", err);
+        });
+    }
+
+    #[test]
+    fn require_of_a_granted_internal_module() {
+        let dir = temp_dir(&[("main.cjs", "module.exports = require(\"@avdan/shell\");")]);
+
+        with_scope(vec!["avdan.shell.*"], |scope| {
+            let exports = AvModCJS::require_path(scope, &dir.join("main.cjs")).unwrap();
+            assert!(exports.is_object());
+        });
+    }
+
+    #[test]
+    fn thrown_values_are_kept() {
+        let dir = temp_dir(&[
+            ("main.cjs", "require(\"./throws.cjs\");"),
+            ("throws.cjs", "throw { code: 42 };"),
+        ]);
+
+        with_scope(vec![], |scope| {
+            let scope = &mut v8::TryCatch::new(scope);
+
+            assert!(AvModCJS::require_path(scope, &dir.join("main.cjs")).is_none());
+
+            // The very object thrown, not a string of it.
+            let thrown : v8::Local<v8::Object> = scope.exception().unwrap().try_into().unwrap();
+            let code = crate::core::obj_get_property(scope, thrown, "code");
+
+            assert_eq!(code.integer_value(scope), Some(42));
+        });
+    }
+
+    #[test]
+    fn syntax_errors_keep_their_location() {
+        let dir = temp_dir(&[("main.cjs", "module.exports = {;")]);
+
+        with_scope(vec![], |scope| {
+            let scope = &mut v8::TryCatch::new(scope);
+
+            assert!(AvModCJS::require_path(scope, &dir.join("main.cjs")).is_none());
+
+            let message = scope.message().unwrap();
+            let line = message.get_line_number(scope);
+
+            assert!(scope.exception().unwrap().to_rust_string_lossy(scope).starts_with("SyntaxError"));
+            assert_eq!(line, Some(1));
+        });
+    }
+}
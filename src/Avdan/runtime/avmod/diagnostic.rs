@@ -0,0 +1,244 @@
+use std::{fmt::Display, path::Path};
+
+use colored::Colorize;
+use serde::Serialize;
+use v8::{HandleScope, Local, Value};
+
+use crate::Avdan::runtime::SourceMaps;
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    /// A file which couldn't be found or read.
+    Io,
+    /// A specifier which couldn't be parsed or resolved.
+    Resolve,
+    /// A syntax error (or any other error while compiling).
+    Compile,
+    /// An error while linking/instantiating a module.
+    Instantiate,
+    /// A file format, or import type, the loader doesn't know.
+    Unsupported,
+    /// An import the extension isn't allowed to make.
+    Security,
+}
+
+impl DiagnosticKind {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Io          => "I/O error",
+            Self::Resolve     => "Resolution error",
+            Self::Compile     => "Compile error",
+            Self::Instantiate => "Instantiation error",
+            Self::Unsupported => "Unsupported module",
+            Self::Security    => "Security error",
+        }
+    }
+}
+
+///
+/// ## Diagnostic
+///
+/// A module loader failure: what went wrong, where, and through which imports the loader got there.
+///
+
+#[derive(Clone, Serialize)]
+pub struct Diagnostic {
+    pub kind         : DiagnosticKind,
+    pub message      : String,
+    pub file         : Option<String>,
+    pub line         : Option<u32>,
+    pub column       : Option<u32>,
+    /// The files which (transitively) imported `file`, closest first.
+    pub import_chain : Vec<String>,
+    pub code_frame   : Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(kind: DiagnosticKind, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            kind,
+            message      : message.into(),
+            file         : None,
+            line         : None,
+            column       : None,
+            import_chain : vec![],
+            code_frame   : None,
+        }
+    }
+
+    pub fn in_file(mut self, file: &Path) -> Diagnostic {
+        self.file = Some(file.to_str().unwrap().to_string());
+        self
+    }
+
+    /// Sets the (1-based) position, and the code frame around it.
+    pub fn at(mut self, source: &str, line: u32, column: u32) -> Diagnostic {
+        self.line   = Some(line);
+        self.column = Some(column);
+        self.code_frame = Self::code_frame(source, line, column);
+        self
+    }
+
+    /// Points a diagnostic in generated JavaScript (e.g. emitted from TypeScript)
+    /// back at the original source, if the file has a source map.
+    pub fn remap<'a>(mut self, scope: &mut HandleScope<'a>) -> Diagnostic {
+        let (file, line, column) = match (&self.file, self.line, self.column) {
+            (Some(f), Some(l), Some(c)) => (f.clone(), l, c),
+            _                           => return self,
+        };
+
+        let maps = match scope.get_slot::<SourceMaps>() {
+            Some(m) => m,
+            None    => return self,
+        };
+
+        if let Some(mapped) = maps.remap(&file, line, column) {
+            self.code_frame = maps.source_contents(&file, &mapped.source)
+                .and_then(|source| Self::code_frame(source, mapped.line, mapped.column));
+            self.line   = Some(mapped.line);
+            self.column = Some(mapped.column);
+        }
+
+        self
+    }
+
+    /// Records that the module which failed was imported by `file`.
+    pub fn imported_from(mut self, file: &Path) -> Diagnostic {
+        self.import_chain.push(file.to_str().unwrap().to_string());
+        self
+    }
+
+    /// Builds a diagnostic from an exception thrown by V8 (e.g. a `SyntaxError`).
+    pub fn from_exception<'a>(scope: &mut HandleScope<'a>, kind: DiagnosticKind, file: &Path, source: &str, exception: Local<Value>) -> Diagnostic {
+        let message = v8::Exception::create_message(scope, exception);
+        let text = message.get(scope).to_rust_string_lossy(scope);
+
+        let d = Diagnostic::new(kind, text).in_file(file);
+
+        match message.get_line_number(scope) {
+            Some(line) => d.at(source, line as u32, message.get_start_column() as u32 + 1),
+            None       => d,
+        }
+    }
+
+    fn code_frame(source: &str, line: u32, column: u32) -> Option<String> {
+        let lines : Vec<&str> = source.lines().collect();
+        let index = (line as usize).checked_sub(1)?;
+
+        lines.get(index)?;
+
+        let first = index.saturating_sub(2);
+        let last  = (index + 2).min(lines.len() - 1);
+        let width = (last + 1).to_string().len();
+
+        let mut frame = vec![];
+
+        for i in first..=last {
+            let marker = if i == index { ">" } else { " " };
+            frame.push(format!("{} {:>width$} | {}", marker, i + 1, lines[i], width = width));
+
+            if i == index {
+                frame.push(format!("  {:>width$} | {}^", "", " ".repeat(column.saturating_sub(1) as usize), width = width));
+            }
+        }
+
+        Some(frame.join("\n"))
+    }
+
+    /// Machine-readable form, for hosts (`--diagnostics=json`).
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+impl From<String> for Diagnostic {
+    fn from(message: String) -> Self {
+        Diagnostic::new(DiagnosticKind::Resolve, message)
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind.name().bright_red().bold(), self.message)?;
+
+        if let Some(file) = &self.file {
+            match (self.line, self.column) {
+                (Some(l), Some(c)) => write!(f, "\n  {} {}:{}:{}", "-->".blue(), file, l, c)?,
+                _                  => write!(f, "\n  {} {}", "-->".blue(), file)?,
+            }
+        }
+
+        if let Some(frame) = &self.code_frame {
+            write!(f, "\n\n{}\n", frame)?;
+        }
+
+        for importer in self.import_chain.iter() {
+            write!(f, "\n  {} {}", "imported by".yellow(), importer)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Diagnostic, DiagnosticKind};
+    use crate::Avdan::runtime::avmod::testing::with_scope;
+
+    const SOURCE : &str = "let a = 1;\nlet b = 2;\nlet c = ;\nlet d = 4;\nlet e = 5;\nlet f = 6;";
+
+    #[test]
+    fn code_frames() {
+        let d = Diagnostic::new(DiagnosticKind::Compile, "Unexpected token").at(SOURCE, 3, 9);
+
+        assert_eq!(d.code_frame.as_deref(), Some(concat!(
+            "  1 | let a = 1;\n",
+            "  2 | let b = 2;\n",
+            "> 3 | let c = ;\n",
+            "    |         ^\n",
+            "  4 | let d = 4;\n",
+            "  5 | let e = 5;",
+        )));
+    }
+
+    #[test]
+    fn positions_outside_the_source_have_no_frame() {
+        let d = Diagnostic::new(DiagnosticKind::Compile, "?").at(SOURCE, 7, 1);
+
+        assert_eq!(d.line, Some(7));
+        assert!(d.code_frame.is_none());
+    }
+
+    #[test]
+    fn json() {
+        let d = Diagnostic::new(DiagnosticKind::Security, "Not allowed")
+            .in_file(Path::new("/ext/b.js"))
+            .imported_from(Path::new("/ext/a.js"))
+            .imported_from(Path::new("/ext/main.js"));
+
+        let json : serde_json::Value = serde_json::from_str(&d.to_json()).unwrap();
+
+        assert_eq!(json["kind"], "security");
+        assert_eq!(json["file"], "/ext/b.js");
+        assert_eq!(json["import_chain"], serde_json::json!(["/ext/a.js", "/ext/main.js"]));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        with_scope(vec![], |scope| {
+            let scope = &mut v8::TryCatch::new(scope);
+            let code = v8::String::new(scope, SOURCE).unwrap();
+
+            assert!(v8::Script::compile(scope, code, None).is_none());
+
+            let exception = scope.exception().unwrap();
+            let d = Diagnostic::from_exception(scope, DiagnosticKind::Compile, Path::new("/ext/main.js"), SOURCE, exception);
+
+            assert!(d.message.contains("SyntaxError"), "{}", d.message);
+            assert_eq!((d.line, d.column), (Some(3), Some(9)));
+        });
+    }
+}
@@ -1,52 +1,155 @@
 use colored::Colorize;
-use v8::{HandleScope, Global, Module, TryCatch, Local, Context, Value, CallbackScope};
-use crate::Avdan::{api::AvdanAPI, utils};
-use super::{Specifier, AvModStore};
+use v8::{HandleScope, Global, Module, TryCatch, Local, Context, Value, CallbackScope, Object};
+use crate::{Avdan::{api::AvdanAPI, runtime::Lockdown, security::Constraints, utils}, core::obj_get_property};
+use super::{Specifier, AvMod, AvModStore, Diagnostic, DiagnosticKind};
+
+///
+/// ## AvModInternal
+///
+/// The `@avdan` modules:
+/// * `@avdan` -- every API the extension may use as a named export (`import { Debug, Net } from "@avdan"`).
+/// * `@avdan/<api>` -- a single API, e.g. `@avdan/net`.
+/// * `@avdan/<api>/<sub>` -- part of an API, e.g. `@avdan/net/fetch`.
+///
+/// Importing (or `require`-ing) an API the extension has no permission for fails -- an import when
+/// the module is linked.
+///
 
 pub struct AvModInternal {}
 
 impl AvModInternal {
-    pub fn get_internal_module<'a>(scope: &mut HandleScope<'a>, name: String) -> Result<Global<Module>, String> {
+    /// The permission namespace an internal module falls under,
+    /// e.g. `net/fetch` -> `avdan.net.fetch`.
+    fn permission(id: &str) -> Option<String> {
+        let api = id.split("/").next().unwrap_or("");
+
+        match api.is_empty() || !AvdanAPI::is_privileged(api) {
+            true  => None,
+            false => Some(format!("avdan.{}", id.replace("/", ".")))
+        }
+    }
+
+    /// Whether the extension may import an internal module (or use it through `@avdan`).
+    fn allowed<'a>(scope: &mut HandleScope<'a>, id: &str) -> bool {
+        match Self::permission(id) {
+            Some(perm) => Constraints::from_scope(scope).has_any_permission(&perm),
+            None       => true,
+        }
+    }
+
+    /// Fails if the extension may not use an internal module -- checked by every way in (`import`, `require`).
+    fn check_permission<'a>(scope: &mut HandleScope<'a>, id: &str) -> Result<(), String> {
+        match Self::allowed(scope, id) {
+            true  => Ok(()),
+            false => Err(format!(
+                "SecurityException -- Invalid permissions!\nYour extension needs '{}' (or one of its children) to use `{}`.",
+                Self::permission(id).unwrap_or_default(),
+                Specifier::Internal(id.to_string()).path()
+            )),
+        }
+    }
+
+    /// `@avdan` itself: `{ Debug, Shell, Net, ... }`, without the APIs the extension has no permission for
+    /// -- importing one of those fails when the module is linked, as `@avdan/<api>` does.
+    fn root_object<'a>(scope: &mut HandleScope<'a>) -> Local<'a, Object> {
+        let obj = Object::new(scope);
+        let mut apis : Vec<_> = AvdanAPI::public_apis().into_iter()
+            .filter(|(name, _)| Self::allowed(scope, name))
+            .collect();
+
+        apis.sort_by_key(|(name, _)| *name);
+
+        for (name, api) in apis {
+            let k = v8::String::new(scope, AvdanAPI::export_name(name).as_str()).unwrap();
+            let v = api.js(scope);
+
+            obj.define_own_property(scope, k.into(), v.into(), v8::READ_ONLY);
+        }
+
+        obj
+    }
+
+    /// Builds the JS value of an internal module (`@avdan`, `@avdan/<api>` or `@avdan/<api>/<sub>`),
+    /// frozen if the context is locked down -- if the extension has the permission for it.
+    pub fn api_object<'a>(scope: &mut HandleScope<'a>, name: String) -> Result<Local<'a, Value>, String> {
+        Self::check_permission(scope, &name)?;
+
+        let value = Self::build_api_object(scope, name)?;
+
+        Ok(Lockdown::harden(scope, value))
+    }
+
+    fn build_api_object<'a>(scope: &mut HandleScope<'a>, name: String) -> Result<Local<'a, Value>, String> {
+        if name.is_empty() {
+            return Ok(Self::root_object(scope).into());
+        }
+
+        let not_found = || format!("{}{} {}", "internal module @avdan/".bright_red(), name.as_str().yellow(), "not found!".bright_red());
+
+        let mut path = name.split("/");
         let apis = AvdanAPI::public_apis();
 
-        match apis.get(name.as_str()) {
-            Some(api) => {
-                let obj = api.as_ref().js(scope);
-                let mut export_names = vec![v8::String::new(scope, "default").unwrap()];
-                let names = obj.get_own_property_names(scope).unwrap();
+        let api = apis.get(path.next().unwrap()).ok_or_else(not_found)?;
+        let mut value : Local<Value> = api.js(scope).into();
 
-                for name in utils::array_to_vec::<v8::String>(scope, names) {
-                    export_names.push(name);
-                }
-                
-                let _name = name.clone();
-                let name  = v8::String::new(scope, name.as_str()).unwrap();
-                let scope = &mut TryCatch::new(scope);
-
-                let module = Module::create_synthetic_module (
-                    scope,
-                    name,
-                    export_names.as_slice(),
-                    Self::evaluation_steps
-                );
-
-                {
-                    let g = Global::new(scope, module);
-                    let store  =scope.get_slot_mut::<AvModStore>().unwrap();
-                    store.add_internal(g, _name);
-                }
+        for key in path {
+            let obj : Local<Object> = value.try_into().map_err(|_| not_found())?;
+            value = obj_get_property(scope, obj, key);
 
-                match module.instantiate_module(scope, Self::instantiate_callback) {
-                    None => {
-                        let excep = scope.exception().unwrap();
-                        Err(format!("Error from JS:\n\t{}", excep.to_rust_string_lossy(scope).as_str().bright_red()))
-                    },
-                    
-                    Some(r) => Ok(Global::new(scope, module))
-                }
+            if value.is_undefined() {
+                return Err(not_found());
+            }
+        }
+
+        Ok(value)
+    }
+
+    /// Named exports of an internal module -- the own properties of plain objects, none for functions.
+    fn export_names<'a>(scope: &mut HandleScope<'a>, value: Local<Value>) -> Vec<Local<'a, v8::String>> {
+        if value.is_function() || !value.is_object() {
+            return vec![];
+        }
+
+        let obj : Local<Object> = value.try_into().unwrap();
+        let names = obj.get_own_property_names(scope).unwrap();
+
+        utils::array_to_vec::<v8::String>(scope, names)
+    }
+
+    pub fn get_internal_module<'a>(scope: &mut HandleScope<'a>, name: String) -> Result<Global<Module>, Diagnostic> {
+        Self::check_permission(scope, &name)
+            .map_err(|err| Diagnostic::new(DiagnosticKind::Security, err))?;
+
+        let value = Self::api_object(scope, name.clone())
+            .map_err(|_| Diagnostic::new(DiagnosticKind::Resolve, format!("Internal module `{}` not found!", Specifier::Internal(name.clone()).path())))?;
+
+        let mut export_names = vec![v8::String::new(scope, "default").unwrap()];
+        export_names.extend(Self::export_names(scope, value));
+
+        let _name = name.clone();
+        let name  = v8::String::new(scope, Specifier::Internal(name).path().as_str()).unwrap();
+        let scope = &mut TryCatch::new(scope);
+
+        let module = Module::create_synthetic_module (
+            scope,
+            name,
+            export_names.as_slice(),
+            Self::evaluation_steps
+        );
+
+        {
+            let g = Global::new(scope, module);
+            let store  =scope.get_slot_mut::<AvModStore>().unwrap();
+            store.add_internal(g, _name);
+        }
+
+        match module.instantiate_module(scope, Self::instantiate_callback) {
+            None => {
+                let excep = scope.exception().unwrap();
+                Err(Diagnostic::new(DiagnosticKind::Instantiate, excep.to_rust_string_lossy(scope)))
             },
             
-            None => Err(format!("{}{} {}", "internal module @avdan/".bright_red(), name.as_str().yellow(), "not found!".bright_red()))
+            Some(r) => Ok(Global::new(scope, module))
         }
     }
 
@@ -70,37 +173,44 @@ impl AvModInternal {
 
         let g = Global::new(scope, module);
 
-        let store  = scope.get_slot_mut::<AvModStore>().unwrap();
-
-        let s = &mut unsafe {
-            CallbackScope::new(ctx)
+        let name = match scope.get_slot::<AvModStore>().and_then(|s| s.get_internal(&g)).cloned() {
+            Some(n)   => n,
+            None      => {
+                AvMod::fail(scope, Diagnostic::new(DiagnosticKind::Instantiate, "Internal module has no name!"));
+                return None;
+            }
         };
-        
-        let try_catch = &mut TryCatch::new(s);
 
-        let name = store.get_internal(&g).expect("Expected same name!");
-
-        // let name = "clipboard".to_string();
-        let apis = AvdanAPI::public_apis();
-        let api = apis.get(name.as_str()).unwrap();
-
-        let obj = api.js(try_catch);
+        let value = match Self::api_object(scope, name) {
+            Ok(v)    => v,
+            Err(err) => {
+                AvMod::fail(scope, Diagnostic::new(DiagnosticKind::Resolve, err));
+                return None;
+            }
+        };
 
-        let keys = obj.get_own_property_names(try_catch).unwrap();
+        let try_catch = &mut TryCatch::new(scope);
 
-        for key in utils::array_to_vec::<v8::String>(try_catch, keys) {
-            let value = obj.get(try_catch, key.into()).unwrap();
+        for key in Self::export_names(try_catch, value) {
+            let obj : Local<Object> = value.try_into().unwrap();
+            let export = match obj.get(try_catch, key.into()) {
+                Some(e) => e,
+                None    => {
+                    try_catch.rethrow();
+                    return None;
+                }
+            };
             let res = module.set_synthetic_module_export(
                 try_catch,
                 key,
-                value
+                export
             );
 
             match res {
                 Some(_) => {},
                 None => {
-                    let excep =try_catch.exception().unwrap();
-                    panic!("Error from JS:\n\t{}\n", excep.to_rust_string_lossy(scope).bright_red());
+                    try_catch.rethrow();
+                    return None;
                 }
             }
         } 
@@ -110,12 +220,12 @@ impl AvModInternal {
         match module.set_synthetic_module_export (
             try_catch,
             default,
-            obj.into()
+            value
         ) {
             Some(_) => {},
             None => {
-                let excep =try_catch.exception().unwrap();
-                panic!("Error from JS:\n\t{}\n", excep.to_rust_string_lossy(try_catch).bright_red());
+                try_catch.rethrow();
+                return None;
             }
         }
 
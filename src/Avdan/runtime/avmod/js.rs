@@ -1,11 +1,11 @@
 use std::{path::PathBuf, sync::mpsc::channel};
 
 use colored::Colorize;
-use v8::{TryCatch, HandleScope, Global, Module, ScriptOrigin, script_compiler::Source, CallbackScope, ModuleRequest, Value, ModuleStatus, Promise, Local, PromiseState};
+use v8::{TryCatch, HandleScope, Global, Module, ScriptOrigin, script_compiler::{Source, CachedData, CompileOptions, NoCacheReason}, CallbackScope, ModuleRequest, Value, ModuleStatus, Promise, Local, PromiseState};
 
-use crate::Avdan::{utils, api::debug::AvDebug, runtime::avmod::AvModStore};
+use crate::Avdan::{utils, api::debug::AvDebug, loader::Package, runtime::{avmod::{AvModStore, ModuleKey}, SourceMaps}};
 
-use super::{AvModProvider, AvMod, Specifier};
+use super::{AvModProvider, AvMod, Specifier, CodeCache, Diagnostic, DiagnosticKind};
 
 pub struct AvModJS {}
 
@@ -31,39 +31,66 @@ impl AvModJS {
             true
         )
     }
-}
 
-impl AvModProvider for AvModJS {
-    fn load_module<'a> (
-        scope : &mut TryCatch<HandleScope<'a>>,
-        path  : &PathBuf
-    ) -> Result<Global<Module>, String> {
+    /// Compiles, links and instantiates an ES module from already-loaded source text.
+    /// 
+    /// Used by [`AvModJS::load_module`], and by providers which transform
+    /// their files into JavaScript first (e.g. [`super::AvModTS`]).
+    pub fn load_module_from_source<'a> (
+        scope  : &mut TryCatch<HandleScope<'a>>,
+        path   : &PathBuf,
+        source : &str
+    ) -> Result<Global<Module>, Diagnostic> {
         let source_text = v8::String::new(
             scope,
-            AvMod::load_file(path).as_str()
+            source
         ).unwrap();
 
+        let name = path.to_str().unwrap();
+        let source_map_url = SourceMaps::register(scope, name, path, source);
+
         let origin = AvModJS::default_script_origin(
             scope,
-            path.file_name().unwrap().to_str().unwrap(),
-            ""
+            name,
+            source_map_url.as_str()
         );
 
-        let source_code = Source::new(source_text, Some(&origin));
+        let cache = CodeCache::new(path, source);
+        let cached_data = cache.load();
+
+        let (mut source_code, options) = match &cached_data {
+            Some(data) => (
+                Source::new_with_cached_data(source_text, Some(&origin), CachedData::new(data)),
+                CompileOptions::ConsumeCodeCache
+            ),
+            None => (
+                Source::new(source_text, Some(&origin)),
+                CompileOptions::NoCompileOptions
+            ),
+        };
         
-        let module = match v8::script_compiler::compile_module(scope, source_code) {
+        let module = match v8::script_compiler::compile_module2(scope, &mut source_code, options, NoCacheReason::NoReason) {
             Some(s) => s,
             None => {
                 let err = scope.exception().unwrap();
-                let err = err.to_rust_string_lossy(scope);
-                panic!("\n\t{} `{}`.\n\t{}", 
-                    "Error compiling JavaScript in file".bright_red(), 
-                    path.to_str().unwrap().yellow(),
-                    err.bright_red()
-                );
+                return Err(Diagnostic::from_exception(scope, DiagnosticKind::Compile, path, source, err).remap(scope));
             }
         };
 
+        let rejected = source_code.get_cached_data()
+            .map(|c| c.rejected())
+            .unwrap_or(false);
+
+        if cached_data.is_none() || rejected {
+            if rejected {
+                cache.invalidate();
+            }
+
+            if let Some(data) = module.get_unbound_module_script(scope).create_code_cache() {
+                cache.store(&data);
+            }
+        }
+
         // println!("");
         // println! (
         //     "[{1}] {0}:",
@@ -72,22 +99,46 @@ impl AvModProvider for AvModJS {
         //     path.file_name().unwrap().to_str().unwrap().to_string().yellow()
         // );
 
+        // Registered before its imports are loaded, so one importing it back gets this very module.
+        {
+            let g = Global::new(scope, module);
+            let store = scope.get_slot_mut::<AvModStore>().unwrap();
+
+            store.register(ModuleKey::file(path, None), g);
+        }
+
         for import in utils::fixed_array_to_vec::<ModuleRequest>(scope, module.get_module_requests()) {
-            let name = import.get_specifier();
+            let name = import.get_specifier().to_rust_string_lossy(scope);
             
-            let res : Specifier = match name.to_rust_string_lossy(scope).try_into() {
+            let res : Specifier = match name.clone().try_into() {
                 Ok(v) => v,
-                Err(err) => panic!("{}", err)
+                Err(err) => {
+                    let location = module.source_offset_to_location(import.get_source_offset());
+
+                    return Err(
+                        Diagnostic::new(DiagnosticKind::Resolve, err)
+                            .in_file(path)
+                            .at(source, location.get_line_number() as u32 + 1, location.get_column_number() as u32 + 1)
+                            .remap(scope)
+                    );
+                }
             };   
             
             // println!("   {}\t{}", Colorize::bright_red("*").bold(), res);
 
-            let dependency = AvMod::load(
-                scope,
-                &path.parent().unwrap().canonicalize().unwrap(), res.clone())?;
+            let import_type = AvMod::import_type(scope, import);
+
+            let dir = path.parent()
+                .ok_or_else(|| Diagnostic::new(DiagnosticKind::Resolve, "Module has no parent folder!").in_file(path))
+                .and_then(|d| Package::canonicalize(d).map_err(|e| Diagnostic::new(DiagnosticKind::Io, e.to_string()).in_file(path)))?;
 
-            let store  =scope.get_slot_mut::<AvModStore>().unwrap();
-            store.register(res, dependency);
+            let dependency = AvMod::load(scope, &dir, res, import_type.clone())
+                .map_err(|d| d.imported_from(path))?;
+
+            let referrer = Global::new(scope, module);
+            let store = scope.get_slot_mut::<AvModStore>().unwrap();
+
+            store.link(referrer, name, import_type, dependency);
         }
         
         // println!("");
@@ -97,15 +148,29 @@ impl AvModProvider for AvModJS {
             Self::_instantiate_callback
         ) {
             None => {
-                let excep = scope.exception().unwrap();
-                let s= excep.to_rust_string_lossy(scope);
-                panic!("Error from JS:\n\t{}\n", s.bright_red())
+                // A failure in `_instantiate_callback` comes with its own diagnostic.
+                let failure = scope.get_slot_mut::<AvModStore>().and_then(|s| s.take_failure());
+
+                return Err(match (failure, scope.exception()) {
+                    (Some(d), _)    => d.imported_from(path),
+                    (None, Some(e)) => Diagnostic::from_exception(scope, DiagnosticKind::Instantiate, path, source, e).remap(scope),
+                    (None, None)    => Diagnostic::new(DiagnosticKind::Instantiate, "Could not link module!").in_file(path),
+                });
             },
             _ => {}
         };
 
         Ok(Global::new(scope, module))
     }
+}
+
+impl AvModProvider for AvModJS {
+    fn load_module<'a> (
+        scope : &mut TryCatch<HandleScope<'a>>,
+        path  : &PathBuf
+    ) -> Result<Global<Module>, Diagnostic> {
+        Self::load_module_from_source(scope, path, AvMod::load_file(path)?.as_str())
+    }
 
     fn _instantiate_callback<'a> (
         context           : v8::Local<'a, v8::Context>,
@@ -120,16 +185,21 @@ impl AvModProvider for AvModJS {
         // println!("[{}] {}", specifier.to_rust_string_lossy(scope).yellow(), Colorize::blue("Callback from instantiation"), );
         // println!("[{}] Import assertions: {}", specifier.to_rust_string_lossy(scope).yellow(), import_assertions.length());
         
-        let res : Specifier = specifier.to_rust_string_lossy(scope).try_into().unwrap();
-        let store = scope.get_slot_mut::<AvModStore>().unwrap();
-        
-        let sender    = store.get_sender(&dependent.script_id().unwrap());
-        let dependency = store.get(&res).expect("Could not find loaded module (!)");
-
-        let s = &mut unsafe {
-            CallbackScope::new(context)
-        };
-        
-        Some(Local::new(s, dependency))
+        let specifier = specifier.to_rust_string_lossy(scope);
+        let import_type = AvMod::resolved_import_type(scope, import_assertions);
+        let referrer = Global::new(scope, dependent);
+
+        // What this very import (from this module, with this assertion) was loaded as.
+        let dependency = scope.get_slot::<AvModStore>()
+            .and_then(|s| s.linked(referrer, specifier.clone(), import_type))
+            .cloned();
+
+        match dependency {
+            Some(dependency) => Some(Local::new(scope, dependency)),
+            None             => {
+                AvMod::fail(scope, Diagnostic::new(DiagnosticKind::Instantiate, format!("Module `{}` was not loaded!", specifier)));
+                None
+            }
+        }
     }
 }
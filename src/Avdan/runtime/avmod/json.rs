@@ -5,7 +5,7 @@ use v8::{Exception, Local, Value, Global, Module, Context, HandleScope, Callback
 
 use crate::Avdan::utils;
 
-use super::{AvModProvider, AvMod, AvModStore};
+use super::{AvModProvider, AvMod, AvModStore, Diagnostic, DiagnosticKind};
 
 pub struct AvModJSON {}
 
@@ -19,8 +19,13 @@ impl AvModJSON {
         };
 
         let g = Global::new(scope, module);
-        let store  = scope.get_slot_mut::<AvModStore>().unwrap();
-        let obj = store.get_json(&g).unwrap();
+        let obj = match scope.get_slot_mut::<AvModStore>().and_then(|s| s.get_json(&g)) {
+            Some(obj) => obj,
+            None      => {
+                AvMod::fail(scope, Diagnostic::new(DiagnosticKind::Instantiate, "JSON module has no value!"));
+                return None;
+            }
+        };
 
         let s = &mut unsafe {
             CallbackScope::new(ctx)
@@ -39,8 +44,8 @@ impl AvModJSON {
         ) {
             Some(_) => {},
             None    => {
-                let excep =try_catch.exception().unwrap();
-                panic!("Error from JS:\n\t{}\n", excep.to_rust_string_lossy(try_catch).bright_red());
+                try_catch.rethrow();
+                return None;
             }
         }
 
@@ -52,15 +57,19 @@ impl AvModProvider for AvModJSON {
     fn load_module<'a> (
         scope : &mut v8::TryCatch<v8::HandleScope<'a>>,
         path  : &std::path::PathBuf
-    ) -> Result<Global<Module>, String> {
-        let contents = AvMod::load_file(path);
+    ) -> Result<Global<Module>, Diagnostic> {
+        let contents = AvMod::load_file(path)?;
         let contents_str = v8::String::new(scope, &contents).unwrap();
 
-        let json : Local<Object> = v8::json::parse(scope, contents_str).unwrap().try_into().unwrap();
-        
-        if scope.has_caught() {
-            return Err("Error while parsing json!".into());
-        }
+        let json : Local<Object> = match v8::json::parse(scope, contents_str).and_then(|j| j.try_into().ok()) {
+            Some(json) => json,
+            None       => {
+                return Err(match scope.exception() {
+                    Some(e) => Diagnostic::from_exception(scope, DiagnosticKind::Compile, path, &contents, e),
+                    None    => Diagnostic::new(DiagnosticKind::Compile, "JSON module must be an object!").in_file(path),
+                });
+            }
+        };
 
         let export_names = [v8::String::new(scope, "default").unwrap()];
 
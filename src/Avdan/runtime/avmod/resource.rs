@@ -2,13 +2,15 @@ use std::{path::{PathBuf, Path}, fmt::Display, hash::Hash};
 
 use colored::Colorize;
 
+use crate::Avdan::loader::Package;
+
 #[derive(PartialEq, Eq, Clone)]
 pub struct SourceFile {
     path: String,
 }
 
 impl SourceFile {
-    fn new(path: String) -> SourceFile {
+    pub(super) fn new(path: String) -> SourceFile {
         SourceFile {
             path
         }
@@ -17,10 +19,14 @@ impl SourceFile {
     pub fn to_path(&self, root_path: String) -> Result<PathBuf, String> {
         let mut p = Path::new(root_path.as_str()).join(self.path.clone());
 
-        if !p.exists() {
+        if !Package::exists(&p) {
             p.set_extension("js");
             
-            if !p.exists() {
+            if !Package::exists(&p) {
+                p.set_extension("ts");
+            }
+
+            if !Package::exists(&p) {
                 return Err(format!("{}\nPath does not exist !", p.to_str().unwrap()));
             }
         }
@@ -33,11 +39,18 @@ impl SourceFile {
     } 
 
     pub fn extension(&self) -> String {
-        Path::new(&self.path).extension().unwrap().to_str().unwrap_or_else(|| "js").to_string()
+        Path::new(&self.path).extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_string()
     }
 
     pub fn is_js_file(&self) -> bool {
-        self.extension() == "js" || self.extension() == "mjs"
+        self.extension() == "js" || self.extension() == "mjs" || self.extension() == "cjs"
+    }
+
+    pub fn is_ts_file(&self) -> bool {
+        self.extension() == "ts" || self.extension() == "mts" || self.extension() == "tsx"
     }
 }
 
@@ -90,7 +103,11 @@ impl TryFrom<String> for Specifier {
     fn try_from(value: String) -> Result<Self, Self::Error> {
         match value {
             v if v.is_empty()            => Err("Empty resource path!".to_string()),
-            v if v.starts_with("@avdan") => Ok(Self::Internal(v[7..].to_string())),
+            v if v == "@avdan"            => Ok(Self::Internal("".to_string())),
+            v if v.starts_with("@avdan/") => match v[7..].trim_end_matches("/") {
+                ""  => Err(format!("Missing API name in `{}`!", v)),
+                id  => Ok(Self::Internal(id.to_string())),
+            },
             v if v.starts_with(".")
                 || v.starts_with("/")    => Ok(Self::File(SourceFile::new(v))),
             v => Ok(Self::Module(ExternalModule::new(v)))
@@ -98,6 +115,18 @@ impl TryFrom<String> for Specifier {
     }
 }
 
+impl Specifier {
+    /// The specifier as written in an import, e.g. `@avdan/net/fetch`.
+    pub fn path(&self) -> String {
+        match self {
+            Self::File(file)                    => file.path.clone(),
+            Self::Internal(id) if id.is_empty() => "@avdan".to_string(),
+            Self::Internal(id)                  => format!("@avdan/{}", id),
+            Self::Module(module)                => module.identifier.clone(),
+        }
+    }
+}
+
 impl Display for Specifier {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -1,16 +1,59 @@
-use std::{collections::HashMap, sync::mpsc::{Sender, Receiver, channel}};
+use std::{collections::HashMap, sync::mpsc::{Sender, Receiver, channel}, path::{Path, PathBuf}};
 
-use v8::{Global, Module, HandleScope, Local, Object};
+use v8::{Global, Module, HandleScope, Local, Object, Value};
 
-use super::Specifier;
+use crate::Avdan::loader::Package;
+
+use super::{Specifier, Diagnostic};
 
 type Key = i32;
 
+///
+/// ## ModuleKey
+///
+/// What a loaded module is cached by: the file it came from (canonical, so `./x.json` and `../lib/x.json`
+/// are the same module) or its `@avdan` id -- and how it was imported, as `./x.json` and
+/// `./x.json` with `assert { type: "text" }` are different modules.
+///
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ModuleKey {
+    path        : String,
+    import_type : Option<String>,
+}
+
+impl ModuleKey {
+    pub fn file(path: &Path, import_type: Option<String>) -> ModuleKey {
+        let path = Package::canonicalize(path).unwrap_or(path.to_path_buf());
+
+        ModuleKey {
+            path : path.to_string_lossy().to_string(),
+            import_type,
+        }
+    }
+
+    pub fn internal(id: &str) -> ModuleKey {
+        ModuleKey {
+            path        : Specifier::Internal(id.to_string()).path(),
+            import_type : None,
+        }
+    }
+}
+
+// An import of one module: the importer, the specifier as written, and its `type` assertion.
+type Link = (Global<Module>, String, Option<String>);
+
 pub(crate) struct AvModStore {
     module_map : HashMap<Key, Sender<i32>>,
-    modules    : HashMap<Specifier, Global<Module>>,
+    modules    : HashMap<ModuleKey, Global<Module>>,
+    links      : HashMap<Link, Global<Module>>,
     internals  : HashMap<Global<Module>, String>,
     json       : HashMap<Global<Module>, Global<Object>>,
+    defaults   : HashMap<Global<Module>, Global<Value>>,
+    cjs        : HashMap<Global<Module>, PathBuf>,
+    cjs_cache  : HashMap<PathBuf, Global<Object>>,
+    /// Why the last V8 callback (link/evaluate) failed, see [`super::AvMod::fail`].
+    failure    : Option<Diagnostic>,
 }
 
 impl AvModStore {
@@ -19,8 +62,13 @@ impl AvModStore {
             AvModStore {
                 module_map : HashMap::new(),
                 modules    : HashMap::new(),
+                links      : HashMap::new(),
                 internals  : HashMap::new(),
-                json       : HashMap::new()
+                json       : HashMap::new(),
+                defaults   : HashMap::new(),
+                cjs        : HashMap::new(),
+                cjs_cache  : HashMap::new(),
+                failure    : None,
             }
         );
     }
@@ -36,16 +84,25 @@ impl AvModStore {
         Some(rx)
     }
 
-    pub fn register<'a>(&mut self, specifier: Specifier, module: Global<Module>) -> () {
-        if self.modules.contains_key(&specifier) {
+    pub fn register<'a>(&mut self, key: ModuleKey, module: Global<Module>) -> () {
+        if self.modules.contains_key(&key) {
             return;
         }
         
-        self.modules.insert(specifier, module);
+        self.modules.insert(key, module);
+    }
+
+    pub fn get(&self, key: &ModuleKey) -> Option<&Global<Module>> {
+        self.modules.get(key)
     }
 
-    pub fn get(&self, specifier: &Specifier) -> Option<&Global<Module>> {
-        self.modules.get(specifier)
+    /// Records which module an import (of `referrer`) resolved to, for V8 to link it.
+    pub fn link(&mut self, referrer: Global<Module>, specifier: String, import_type: Option<String>, module: Global<Module>) -> () {
+        self.links.insert((referrer, specifier, import_type), module);
+    }
+
+    pub fn linked(&self, referrer: Global<Module>, specifier: String, import_type: Option<String>) -> Option<&Global<Module>> {
+        self.links.get(&(referrer, specifier, import_type))
     }
 
     pub fn get_sender(&self, id: &i32) -> Option<Sender<i32>> {
@@ -70,4 +127,57 @@ impl AvModStore {
     pub fn get_json(&mut self, module: &Global<Module>) -> Option<Global<Object>> {
         self.json.remove(module)
     }
+
+    pub fn add_default(&mut self, module: Global<Module>, value: Global<Value>) -> () {
+        self.defaults.insert(module, value);
+    }
+
+    pub fn get_default(&mut self, module: &Global<Module>) -> Option<Global<Value>> {
+        self.defaults.remove(module)
+    }
+
+    pub fn add_cjs(&mut self, module: Global<Module>, path: PathBuf) -> () {
+        self.cjs.insert(module, path);
+    }
+
+    pub fn get_cjs(&self, module: &Global<Module>) -> Option<PathBuf> {
+        self.cjs.get(module).cloned()
+    }
+
+    pub fn add_cjs_module(&mut self, path: PathBuf, module: Global<Object>) -> () {
+        self.cjs_cache.insert(path, module);
+    }
+
+    pub fn get_cjs_module(&self, path: &PathBuf) -> Option<&Global<Object>> {
+        self.cjs_cache.get(path)
+    }
+
+    /// Forgets a module which failed, so the next `require` tries it again (as Node does).
+    pub fn remove_cjs_module(&mut self, path: &PathBuf) -> () {
+        self.cjs_cache.remove(path);
+    }
+
+    pub fn fail(&mut self, diagnostic: Diagnostic) -> () {
+        self.failure = Some(diagnostic);
+    }
+
+    pub fn take_failure(&mut self) -> Option<Diagnostic> {
+        self.failure.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ModuleKey;
+    use crate::Avdan::runtime::avmod::testing::temp_dir;
+
+    #[test]
+    fn module_keys() {
+        let dir = temp_dir(&[("x.json", "{}"), ("sub/y.js", "")]);
+
+        assert!(ModuleKey::file(&dir.join("x.json"), None) == ModuleKey::file(&dir.join("sub/../x.json"), None));
+        assert!(ModuleKey::file(&dir.join("x.json"), None) != ModuleKey::file(&dir.join("x.json"), Some("text".to_string())));
+        assert!(ModuleKey::file(&dir.join("x.json"), Some("json".to_string())) != ModuleKey::file(&dir.join("x.json"), Some("text".to_string())));
+        assert!(ModuleKey::internal("net") != ModuleKey::internal("net/fetch"));
+    }
 }
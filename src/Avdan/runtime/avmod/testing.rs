@@ -0,0 +1,52 @@
+use std::{env, fs, path::PathBuf, sync::{Once, atomic::{AtomicUsize, Ordering}}};
+
+use v8::HandleScope;
+
+use crate::Avdan::{runtime::SourceMaps, security::Constraints};
+
+use super::AvModStore;
+
+static INIT : Once = Once::new();
+static DIRS : AtomicUsize = AtomicUsize::new(0);
+
+/// Runs `f` in a fresh context, set up as the runtime sets up an extension's
+/// (module store, source maps), with the given permissions.
+pub fn with_scope<R>(permissions: Vec<&str>, f: impl FnOnce(&mut HandleScope) -> R) -> R {
+    INIT.call_once(|| {
+        let platform = v8::new_default_platform(0, false).make_shared();
+
+        v8::V8::set_flags_from_string("--harmony-import-assertions");
+        v8::V8::initialize_platform(platform);
+        v8::V8::initialize();
+    });
+
+    let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
+    let handle_scope = &mut v8::HandleScope::new(isolate);
+    let context = v8::Context::new(handle_scope);
+    let scope = &mut v8::ContextScope::new(handle_scope, context);
+
+    SourceMaps::into_scope(scope);
+    Constraints::new(permissions, vec![]).into_scope(scope);
+    AvModStore::into_scope(scope);
+
+    f(scope)
+}
+
+/// A new, empty directory with the given files in it.
+pub fn temp_dir(files: &[(&str, &str)]) -> PathBuf {
+    let dir = env::temp_dir().join(format!(
+        "avdan-js-test-{}-{}", std::process::id(), DIRS.fetch_add(1, Ordering::SeqCst)
+    ));
+
+    let _ = fs::remove_dir_all(&dir);
+
+    for (name, contents) in files {
+        let path = dir.join(name);
+
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fs::create_dir_all(&dir).unwrap();
+    dir.canonicalize().unwrap()
+}
@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+
+use colored::Colorize;
+use v8::{Local, Value, Global, Module, Context, HandleScope, CallbackScope, TryCatch};
+
+use super::{AvModProvider, AvMod, AvModStore, Diagnostic, DiagnosticKind};
+
+///
+/// ## AvModText
+///
+/// `import tpl from "./row.html" assert { type: "text" };`
+///
+/// Exposes the contents of the file as a string (default export).
+///
+
+pub struct AvModText {}
+
+impl AvModText {
+    pub(super) fn evaluation_steps<'a> (
+        ctx   : Local<'a, Context>,
+        module: Local<'a, Module>
+    ) -> Option<Local<'a, Value>> {
+        let scope = &mut unsafe {
+            CallbackScope::new(ctx)
+        };
+
+        let g = Global::new(scope, module);
+        let value = match scope.get_slot_mut::<AvModStore>().and_then(|s| s.get_default(&g)) {
+            Some(v)   => v,
+            None      => {
+                AvMod::fail(scope, Diagnostic::new(DiagnosticKind::Instantiate, "Module has no value!"));
+                return None;
+            }
+        };
+
+        let s = &mut unsafe {
+            CallbackScope::new(ctx)
+        };
+        
+        let try_catch = &mut TryCatch::new(s);
+
+        let value = Local::new(try_catch, value);
+
+        let default = v8::String::new(try_catch, "default").unwrap();
+        
+        match module.set_synthetic_module_export (
+            try_catch,
+            default,
+            value
+        ) {
+            Some(_) => {},
+            None    => {
+                try_catch.rethrow();
+                return None;
+            }
+        }
+
+        Some(v8::undefined(try_catch).into())
+    }
+
+    /// Creates a synthetic module whose default export is `value`.
+    pub(super) fn synthetic_module<'a> (
+        scope : &mut TryCatch<HandleScope<'a>>,
+        path  : &PathBuf,
+        value : Local<Value>
+    ) -> Result<Global<Module>, Diagnostic> {
+        let export_names = [v8::String::new(scope, "default").unwrap()];
+
+        let name = v8::String::new(scope, path.as_os_str().to_str().unwrap()).unwrap();
+        let module = v8::Module::create_synthetic_module(scope, name, &export_names, Self::evaluation_steps); 
+
+        {
+            let g       = Global::new(scope, module);
+            let g_value = Global::new(scope, value);
+            let store   = scope.get_slot_mut::<AvModStore>().unwrap();
+            
+            store.add_default(g, g_value);
+        }
+
+        module.instantiate_module(scope, Self::_instantiate_callback);
+
+        Ok(Global::new(scope, module))
+    }
+}
+
+impl AvModProvider for AvModText {
+    fn load_module<'a> (
+        scope : &mut TryCatch<HandleScope<'a>>,
+        path  : &PathBuf
+    ) -> Result<Global<Module>, Diagnostic> {
+        let contents = v8::String::new(scope, AvMod::load_file(path)?.as_str()).unwrap();
+
+        Self::synthetic_module(scope, path, contents.into())
+    }
+
+    fn _instantiate_callback<'a> (
+        context           : v8::Local<'a, v8::Context>,
+        specifier         : v8::Local<'a, v8::String>,
+        import_assertions : v8::Local<'a, v8::FixedArray>,
+        referrer          : v8::Local<'a, v8::Module>,
+    ) -> Option<v8::Local<'a, v8::Module>> {
+        Some(referrer)
+    }
+}
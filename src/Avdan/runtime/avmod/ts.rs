@@ -0,0 +1,161 @@
+use std::{path::PathBuf, fs};
+
+use deno_ast::{EmitOptions, MediaType, ParseParams, SourceTextInfo};
+use sha2::{Digest, Sha256};
+use v8::{TryCatch, HandleScope, Global, Module};
+
+use super::{AvModProvider, AvMod, AvModJS, Diagnostic, DiagnosticKind};
+
+const CACHE_DIR : &str = "avdan-js/ts";
+
+///
+/// ## AvModTS
+///
+/// Loads TypeScript (`.ts`, `.mts`, `.tsx`) modules.
+///
+/// Types are stripped in-process, and the emitted JavaScript
+/// (with an inline source map) is handed over to [`AvModJS`].
+///
+/// The output is cached on disk, keyed by the SHA-256 of the file's name and contents,
+/// so unchanged files are only transpiled once.
+///
+
+pub struct AvModTS {}
+
+impl AvModTS {
+    fn media_type(path: &PathBuf) -> MediaType {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("tsx") => MediaType::Tsx,
+            _           => MediaType::TypeScript,
+        }
+    }
+
+    fn content_hash(path: &PathBuf, source: &str) -> String {
+        let mut hasher = Sha256::new();
+
+        hasher.update(path.to_string_lossy().as_bytes());
+        hasher.update([0u8]);
+        hasher.update(source.as_bytes());
+
+        hex::encode(hasher.finalize())
+    }
+
+    fn cache_path(hash: &str) -> Option<PathBuf> {
+        dirs::cache_dir().map(|d| d.join(CACHE_DIR).join(format!("{}.js", hash)))
+    }
+
+    fn diagnostic(path: &PathBuf, source: &str, err: &deno_ast::Diagnostic) -> Diagnostic {
+        let pos = &err.display_position;
+
+        Diagnostic::new(DiagnosticKind::Compile, err.message())
+            .in_file(path)
+            .at(source, pos.line_number as u32, pos.column_number as u32)
+    }
+
+    /// Strip the types from a TypeScript source file,
+    /// returning JavaScript with an inline source map.
+    pub fn transpile(path: &PathBuf, source: String) -> Result<String, Diagnostic> {
+        let parsed = deno_ast::parse_module(ParseParams {
+            specifier      : format!("file://{}", path.to_str().unwrap()),
+            text_info      : SourceTextInfo::from_string(source.clone()),
+            media_type     : Self::media_type(path),
+            capture_tokens : false,
+            scope_analysis : false,
+            maybe_syntax   : None,
+        }).map_err(|e| Self::diagnostic(path, &source, &e))?;
+
+        let emitted = parsed.transpile(&EmitOptions {
+            inline_source_map : true,
+            inline_sources    : true,
+            ..Default::default()
+        }).map_err(|e| Diagnostic::new(DiagnosticKind::Compile, e.to_string()).in_file(path))?;
+
+        Ok(emitted.text)
+    }
+
+    /// Same as [`AvModTS::transpile`], but goes through the on-disk cache first.
+    pub fn transpile_cached(path: &PathBuf, source: String) -> Result<String, Diagnostic> {
+        let hash = Self::content_hash(path, &source);
+        let cache_file = Self::cache_path(&hash);
+
+        if let Some(cached) = cache_file.as_ref().and_then(|f| fs::read_to_string(f).ok()) {
+            return Ok(cached);
+        }
+
+        let js = Self::transpile(path, source)?;
+
+        // A failure to cache shouldn't stop the module from loading.
+        if let Some(f) = cache_file {
+            let _ = fs::create_dir_all(f.parent().unwrap())
+                .and_then(|_| fs::write(&f, js.as_bytes()));
+        }
+
+        Ok(js)
+    }
+}
+
+impl AvModProvider for AvModTS {
+    fn load_module<'a> (
+        scope : &mut TryCatch<HandleScope<'a>>,
+        path  : &PathBuf
+    ) -> Result<Global<Module>, Diagnostic> {
+        let js = Self::transpile_cached(path, AvMod::load_file(path)?)?;
+
+        AvModJS::load_module_from_source(scope, path, js.as_str())
+    }
+
+    fn _instantiate_callback<'a> (
+        context           : v8::Local<'a, v8::Context>,
+        specifier         : v8::Local<'a, v8::String>,
+        import_assertions : v8::Local<'a, v8::FixedArray>,
+        referrer          : v8::Local<'a, v8::Module>,
+    ) -> Option<v8::Local<'a, v8::Module>> {
+        AvModJS::_instantiate_callback(context, specifier, import_assertions, referrer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::AvModTS;
+    use crate::Avdan::runtime::avmod::DiagnosticKind;
+
+    #[test]
+    fn types_are_stripped() {
+        let path = PathBuf::from("/ext/main.ts");
+        let source = "interface Point { x: number }\nconst p: Point = { x: 1 };\nexport default p as Point;\n";
+
+        let js = AvModTS::transpile(&path, source.to_string()).ok().unwrap();
+
+        assert!(!js.contains("interface"), "{}", js);
+        assert!(!js.contains(": Point"), "{}", js);
+        assert!(!js.contains("as Point"), "{}", js);
+        assert!(js.contains("export default p"), "{}", js);
+        assert!(js.contains("sourceMappingURL=data:application/json;base64,"), "{}", js);
+    }
+
+    #[test]
+    fn tsx_is_transpiled() {
+        let path = PathBuf::from("/ext/view.tsx");
+        let source = "export const view = (name: string) => <b>{name}</b>;\n";
+
+        let js = AvModTS::transpile(&path, source.to_string()).ok().unwrap();
+
+        assert!(!js.contains("<b>"), "{}", js);
+        assert!(!js.contains(": string"), "{}", js);
+    }
+
+    #[test]
+    fn syntax_errors_are_diagnosed() {
+        let path = PathBuf::from("/ext/main.ts");
+        let source = "const a: number = 1;\nconst b = (;\n";
+
+        let diagnostic = AvModTS::transpile(&path, source.to_string()).err().unwrap();
+
+        assert!(diagnostic.kind == DiagnosticKind::Compile);
+        assert_eq!(diagnostic.file.as_deref(), Some("/ext/main.ts"));
+        assert_eq!(diagnostic.line, Some(2));
+        assert!(diagnostic.code_frame.is_some());
+    }
+}
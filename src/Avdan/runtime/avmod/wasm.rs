@@ -0,0 +1,299 @@
+use std::{path::PathBuf, collections::HashMap, sync::Mutex};
+
+use colored::Colorize;
+use sha2::{Digest, Sha256};
+use v8::{Local, Value, Global, Module, Context, HandleScope, CallbackScope, TryCatch, Object, Function, WasmModuleObject, CompiledWasmModule};
+
+use crate::{Avdan::{security::{Constraints, PermissionInfo}, utils, loader::Package}, core::obj_get_property};
+
+use super::{AvModProvider, AvMod, AvModStore, CodeCache, Diagnostic, DiagnosticKind};
+
+const PERMISSION : &str = "avdan.wasm";
+
+// Not guarded by a `#[permission]`, so registered here.
+inventory::submit! {
+    PermissionInfo {
+        name        : PERMISSION,
+        description : "Compile and run WebAssembly.",
+    }
+}
+
+// Compiled modules, keyed by the SHA-256 of their wire bytes.
+// Shared by every isolate in the process (e.g. across `--watch` reloads);
+// between runs, the serialized modules in the [`CodeCache`] are used instead.
+static COMPILED : Mutex<Option<HashMap<String, CompiledWasmModule>>> = Mutex::new(None);
+
+///
+/// ## AvModWasm
+///
+/// `import { parse } from "./parser.wasm";`
+///
+/// Compiles and instantiates a WebAssembly module, exposing its exports as named exports,
+/// and its `instance.exports` object as the default export.
+///
+/// Requires the `avdan.wasm` permission, which also gates `WebAssembly.compile`/`instantiate`.
+///
+/// An import has nothing to give the module, so modules which need imports are refused (naming them)
+/// -- those can be instantiated from their bytes (`assert { type: "bytes" }`) with `WebAssembly.instantiate`.
+///
+
+pub struct AvModWasm {}
+
+impl AvModWasm {
+    /// V8 asks this before compiling any WebAssembly from JS.
+    pub extern "C" fn allow_wasm_code_generation_callback<'a> (
+        context : Local<'a, Context>,
+        _source : Local<'a, v8::String>
+    ) -> bool {
+        let scope = &mut unsafe {
+            CallbackScope::new(context)
+        };
+
+        Constraints::from_scope(scope).allows(scope, PERMISSION)
+    }
+
+    fn compile<'a>(scope: &mut HandleScope<'a>, path: &PathBuf, bytes: Vec<u8>) -> Result<Local<'a, WasmModuleObject>, Diagnostic> {
+        let hash = hex::encode(Sha256::digest(&bytes));
+        let mut compiled = COMPILED.lock().unwrap();
+        let compiled = compiled.get_or_insert_with(HashMap::new);
+
+        if let Some(module) = compiled.get(&hash) {
+            if let Some(module) = WasmModuleObject::from_compiled_module(scope, module) {
+                return Ok(module);
+            }
+        }
+
+        let cache = CodeCache::for_wasm(&bytes);
+
+        // V8 compiles the wire bytes instead, if the serialized module is stale.
+        let module = match cache.load() {
+            Some(serialized) => WasmModuleObject::deserialize_or_compile(scope, &serialized, &bytes),
+            None             => None,
+        };
+
+        let module = match module {
+            Some(m) => m,
+            None    => {
+                let m = WasmModuleObject::compile(scope, &bytes)
+                    .ok_or_else(|| Diagnostic::new(DiagnosticKind::Compile, "Invalid WebAssembly module!").in_file(path))?;
+
+                cache.store(&m.get_compiled_module().serialize());
+                m
+            }
+        };
+
+        compiled.insert(hash, module.get_compiled_module());
+
+        Ok(module)
+    }
+
+    fn web_assembly<'a>(scope: &mut HandleScope<'a>, name: &str) -> Local<'a, Object> {
+        let global = scope.get_current_context().global(scope);
+        let web_assembly : Local<Object> = obj_get_property(scope, global, "WebAssembly").try_into().unwrap();
+
+        obj_get_property(scope, web_assembly, name).try_into().unwrap()
+    }
+
+    /// `WebAssembly.Module.exports(module).map(e => e.name)`
+    fn export_names<'a>(scope: &mut HandleScope<'a>, module: Local<WasmModuleObject>) -> Vec<Local<'a, v8::String>> {
+        let wasm_module = Self::web_assembly(scope, "Module");
+        let exports_fn : Local<Function> = obj_get_property(scope, wasm_module, "exports").try_into().unwrap();
+
+        let descriptors : Local<v8::Array> = exports_fn
+            .call(scope, wasm_module.into(), &[module.into()]).unwrap()
+            .try_into().unwrap();
+
+        utils::array_to_vec::<Object>(scope, descriptors).into_iter()
+            .map(|d| obj_get_property(scope, d, "name").try_into().unwrap())
+            .collect()
+    }
+
+    /// `WebAssembly.Module.imports(module)`, as `` `module.name` (kind) ``.
+    fn import_names<'a>(scope: &mut HandleScope<'a>, module: Local<WasmModuleObject>) -> Vec<String> {
+        let wasm_module = Self::web_assembly(scope, "Module");
+        let imports_fn : Local<Function> = obj_get_property(scope, wasm_module, "imports").try_into().unwrap();
+
+        let descriptors : Local<v8::Array> = imports_fn
+            .call(scope, wasm_module.into(), &[module.into()]).unwrap()
+            .try_into().unwrap();
+
+        utils::array_to_vec::<Object>(scope, descriptors).into_iter()
+            .map(|d| {
+                let module = obj_get_property(scope, d, "module").to_rust_string_lossy(scope);
+                let name   = obj_get_property(scope, d, "name").to_rust_string_lossy(scope);
+                let kind   = obj_get_property(scope, d, "kind").to_rust_string_lossy(scope);
+
+                format!("`{}.{}` ({})", module, name, kind)
+            })
+            .collect()
+    }
+
+    fn evaluation_steps<'a> (
+        ctx   : Local<'a, Context>,
+        module: Local<'a, Module>
+    ) -> Option<Local<'a, Value>> {
+        let scope = &mut unsafe {
+            CallbackScope::new(ctx)
+        };
+
+        let g = Global::new(scope, module);
+        let wasm_module = match scope.get_slot_mut::<AvModStore>().and_then(|s| s.get_default(&g)) {
+            Some(m)   => m,
+            None      => {
+                AvMod::fail(scope, Diagnostic::new(DiagnosticKind::Instantiate, "WebAssembly module was not compiled!"));
+                return None;
+            }
+        };
+
+        let s = &mut unsafe {
+            CallbackScope::new(ctx)
+        };
+
+        let try_catch = &mut TryCatch::new(s);
+
+        let wasm_module = Local::new(try_catch, wasm_module);
+
+        // new WebAssembly.Instance(module, {})
+        let instance_ctor : Local<Function> = Self::web_assembly(try_catch, "Instance").try_into().unwrap();
+        let imports = Object::new(try_catch);
+
+        let instance = match instance_ctor.new_instance(try_catch, &[wasm_module, imports.into()]) {
+            Some(i) => i,
+            None    => {
+                try_catch.rethrow();
+                return None;
+            }
+        };
+
+        let exports : Local<Object> = obj_get_property(try_catch, instance, "exports").try_into().unwrap();
+        let keys = exports.get_own_property_names(try_catch).unwrap();
+
+        for key in utils::array_to_vec::<v8::String>(try_catch, keys) {
+            let value = exports.get(try_catch, key.into()).unwrap();
+
+            if module.set_synthetic_module_export(try_catch, key, value).is_none() {
+                try_catch.rethrow();
+                return None;
+            }
+        }
+
+        let default = v8::String::new(try_catch, "default").unwrap();
+
+        match module.set_synthetic_module_export (
+            try_catch,
+            default,
+            exports.into()
+        ) {
+            Some(_) => {},
+            None    => {
+                try_catch.rethrow();
+                return None;
+            }
+        }
+
+        Some(v8::undefined(try_catch).into())
+    }
+}
+
+impl AvModProvider for AvModWasm {
+    fn load_module<'a> (
+        scope : &mut TryCatch<HandleScope<'a>>,
+        path  : &PathBuf
+    ) -> Result<Global<Module>, Diagnostic> {
+        if !Constraints::from_scope(scope).allows(scope, PERMISSION) {
+            return Err(Diagnostic::new(
+                DiagnosticKind::Security,
+                format!("SecurityException -- Invalid permissions!\nYour extension does not have '{}'.", PERMISSION)
+            ).in_file(path));
+        }
+
+        let bytes = Package::read(path)
+            .map_err(|e| Diagnostic::new(DiagnosticKind::Io, e.to_string()).in_file(path))?;
+        let wasm_module = Self::compile(scope, path, bytes)?;
+
+        // Otherwise, instantiating it would fail with a bare `LinkError`.
+        let imports = Self::import_names(scope, wasm_module);
+
+        if !imports.is_empty() {
+            return Err(Diagnostic::new(
+                DiagnosticKind::Instantiate,
+                format!(
+                    "WebAssembly module needs imports, which `import` can't provide: {}.\nImport its bytes (`assert {{ type: \"bytes\" }}`) and use `WebAssembly.instantiate(bytes, imports)` instead.",
+                    imports.join(", ")
+                )
+            ).in_file(path));
+        }
+
+        let mut export_names = vec![v8::String::new(scope, "default").unwrap()];
+        export_names.extend(Self::export_names(scope, wasm_module));
+
+        let name = v8::String::new(scope, path.as_os_str().to_str().unwrap()).unwrap();
+        let module = v8::Module::create_synthetic_module(scope, name, export_names.as_slice(), Self::evaluation_steps);
+
+        {
+            let g      = Global::new(scope, module);
+            let g_wasm = Global::new(scope, Local::<Value>::from(wasm_module));
+            let store  = scope.get_slot_mut::<AvModStore>().unwrap();
+
+            store.add_default(g, g_wasm);
+        }
+
+        module.instantiate_module(scope, Self::_instantiate_callback);
+
+        Ok(Global::new(scope, module))
+    }
+
+    fn _instantiate_callback<'a> (
+        context           : v8::Local<'a, v8::Context>,
+        specifier         : v8::Local<'a, v8::String>,
+        import_assertions : v8::Local<'a, v8::FixedArray>,
+        referrer          : v8::Local<'a, v8::Module>,
+    ) -> Option<v8::Local<'a, v8::Module>> {
+        Some(referrer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use v8::TryCatch;
+
+    use super::AvModWasm;
+    use crate::Avdan::runtime::avmod::{AvModProvider, testing::{temp_dir, with_scope}};
+
+    const HEADER : [u8; 8] = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+
+    // (type (func)) (import "env" "log" (func (type 0)))
+    const IMPORTS : [u8; 17] = [
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+        0x02, 0x0b, 0x01, 0x03, b'e', b'n', b'v', 0x03, b'l', b'o', b'g', 0x00, 0x00,
+    ];
+
+    fn module(sections: &[u8]) -> Vec<u8> {
+        HEADER.iter().chain(sections).copied().collect()
+    }
+
+    #[test]
+    fn unsatisfied_imports_are_named() {
+        let dir = temp_dir(&[]);
+        let path = dir.join("imports.wasm");
+        fs::write(&path, module(&IMPORTS)).unwrap();
+
+        with_scope(vec!["avdan.wasm"], |scope| {
+            let err = AvModWasm::load_module(&mut TryCatch::new(scope), &path).err().unwrap();
+            assert!(err.message.contains("`env.log` (function)"), "{}", err.message);
+        });
+    }
+
+    #[test]
+    fn modules_without_imports_load() {
+        let dir = temp_dir(&[]);
+        let path = dir.join("empty.wasm");
+        fs::write(&path, module(&[])).unwrap();
+
+        with_scope(vec!["avdan.wasm"], |scope| {
+            assert!(AvModWasm::load_module(&mut TryCatch::new(scope), &path).is_ok());
+        });
+    }
+}
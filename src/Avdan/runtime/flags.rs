@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use crate::Avdan::security::{ConsentProvider, Overrides, consent_provider};
+
+///
+/// ## Flags
+///
+/// The command line flags of `proj <extension> [flags...]`.
+///
+
+#[derive(Clone)]
+pub struct Flags {
+    /// `--module`
+    pub module           : bool,
+    /// `--watch`
+    pub watch            : bool,
+    /// `--diagnostics=json`
+    pub json_diagnostics : bool,
+    /// `--consent=terminal|host|deny`
+    pub consent          : Arc<dyn ConsentProvider>,
+    /// `--require-signed`
+    pub require_signed   : bool,
+    /// `--sandbox`
+    pub sandbox          : bool,
+    /// `--lockdown`
+    pub lockdown         : bool,
+    /// `--allow-*`, `--deny-*`
+    pub overrides        : Overrides,
+}
+
+impl Flags {
+    /// Parses the flags after the extension's path.
+    pub fn parse(args: &[String]) -> Result<Flags, String> {
+        let has   = |flag: &str| args.iter().any(|a| a == flag);
+        let value = |flag: &str| args.iter().find_map(|a| a.strip_prefix(flag)).map(|v| v.to_string());
+
+        let consent = value("--consent=").unwrap_or("terminal".to_string());
+
+        Ok(Flags {
+            module           : has("--module"),
+            watch            : has("--watch"),
+            json_diagnostics : value("--diagnostics=").as_deref() == Some("json"),
+            consent          : consent_provider(&consent)
+                .ok_or_else(|| format!("Unknown consent provider `{}` (expected terminal, host or deny)!", consent))?,
+            require_signed   : has("--require-signed"),
+            sandbox          : has("--sandbox"),
+            lockdown         : has("--lockdown"),
+            overrides        : Overrides::parse(args)?,
+        })
+    }
+}
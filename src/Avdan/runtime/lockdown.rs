@@ -0,0 +1,112 @@
+use v8::{HandleScope, Local, Global, Function, Value, TryCatch};
+
+// Returns `harden`, after hardening the intrinsics and `Avdan`.
+const LOCKDOWN_JS : &str = r#"(function (global) {
+    const { freeze, getPrototypeOf, getOwnPropertyDescriptor, defineProperty } = Object;
+    const { ownKeys } = Reflect;
+    const hardened = new WeakSet();
+
+    function harden(value) {
+        if (value === null || (typeof value !== "object" && typeof value !== "function") || hardened.has(value)) {
+            return value;
+        }
+
+        hardened.add(value);
+        freeze(value);
+        harden(getPrototypeOf(value));
+
+        for (const key of ownKeys(value)) {
+            const desc = getOwnPropertyDescriptor(value, key);
+
+            if ("value" in desc) {
+                harden(desc.value);
+            } else {
+                harden(desc.get);
+                harden(desc.set);
+            }
+        }
+
+        return value;
+    }
+
+    const globals = [
+        "Object", "Function", "Array", "Number", "BigInt", "Boolean", "String", "Symbol",
+        "Error", "EvalError", "RangeError", "ReferenceError", "SyntaxError", "TypeError", "URIError", "AggregateError",
+        "Promise", "RegExp", "Date", "Map", "Set", "WeakMap", "WeakSet", "WeakRef", "FinalizationRegistry",
+        "ArrayBuffer", "SharedArrayBuffer", "DataView", "Int8Array", "Uint8Array", "Uint8ClampedArray",
+        "Int16Array", "Uint16Array", "Int32Array", "Uint32Array", "Float32Array", "Float64Array",
+        "BigInt64Array", "BigUint64Array", "Math", "JSON", "Reflect", "Proxy", "Atomics", "Intl", "WebAssembly",
+        "eval", "isFinite", "isNaN", "parseFloat", "parseInt",
+        "decodeURI", "decodeURIComponent", "encodeURI", "encodeURIComponent", "Avdan",
+    ].filter(name => name in global);
+
+    // Only reachable through syntax, or other intrinsics.
+    const hidden = [
+        getPrototypeOf(function* () {}),
+        getPrototypeOf(async function () {}),
+        getPrototypeOf(async function* () {}),
+        getPrototypeOf([][Symbol.iterator]()),
+        getPrototypeOf(new Map()[Symbol.iterator]()),
+        getPrototypeOf(new Set()[Symbol.iterator]()),
+        getPrototypeOf(""[Symbol.iterator]()),
+        getPrototypeOf(/./[Symbol.matchAll](""))
+    ];
+
+    for (const name of globals) {
+        harden(global[name]);
+        defineProperty(global, name, { writable: false, configurable: false });
+    }
+
+    hidden.forEach(harden);
+
+    return harden;
+})(globalThis)"#;
+
+///
+/// ## Lockdown
+///
+/// `--lockdown` freezes the intrinsics (`Object.prototype`, `Promise.prototype.then`...) and the
+/// `Avdan` API tree once the runtime has set up the context, so an extension can't change
+/// what the runtime's objects -- or its own dependencies -- see.
+///
+/// Keeps `harden` in the isolate, so `@avdan` modules (built on import) are frozen too.
+///
+
+pub struct Lockdown {
+    harden : Global<Function>,
+}
+
+impl Lockdown {
+    /// Hardens the current context. Run after the runtime's setup, and before any extension code.
+    pub fn apply<'a>(scope: &mut HandleScope<'a>) -> Result<(), String> {
+        let scope = &mut TryCatch::new(scope);
+
+        let source = v8::String::new(scope, LOCKDOWN_JS).unwrap();
+        let harden = v8::Script::compile(scope, source, None)
+            .and_then(|script| script.run(scope))
+            .and_then(|f| Local::<Function>::try_from(f).ok());
+
+        match harden {
+            Some(f) => {
+                let harden = Global::new(scope, f);
+                scope.set_slot(Lockdown { harden });
+
+                Ok(())
+            },
+            None => Err(scope.exception().map(|e| e.to_rust_string_lossy(scope)).unwrap_or_default()),
+        }
+    }
+
+    /// Freezes a value (deeply), if the context is locked down.
+    pub fn harden<'a>(scope: &mut HandleScope<'a>, value: Local<'a, Value>) -> Local<'a, Value> {
+        let harden = match scope.get_slot::<Lockdown>() {
+            Some(l) => l.harden.clone(),
+            None    => return value,
+        };
+
+        let harden = Local::new(scope, harden);
+        let recv = v8::undefined(scope);
+
+        harden.call(scope, recv.into(), &[value]).unwrap_or(value)
+    }
+}
@@ -0,0 +1,451 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{io::{AsRawFd, FromRawFd}, net::UnixStream, process::CommandExt},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+    time::Duration,
+};
+
+use colored::Colorize;
+use landlock::{make_bitflags, path_beneath_rules, Access, AccessFs, BitFlags, Ruleset, RulesetAttr, RulesetCreatedAttr, RulesetStatus, ABI};
+use seccompiler::{BpfProgram, SeccompAction, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition, SeccompFilter, SeccompRule};
+use serde::{Serialize, Deserialize};
+
+use crate::Avdan::{
+    Permission,
+    loader::Extension,
+    security::{AuditEntry, AuditLog, Consent, ConsentProvider, Constraints, Grant, Grants, PermissionInfo, SecretAction, Secrets, expand_home},
+};
+use super::Flags;
+
+// The child's end of the socket, as this fd.
+const CHANNEL_FD  : i32 = 3;
+const CHANNEL_ENV : &str = "AVDAN_SANDBOX_FD";
+
+// Not guarded by a `#[permission]` (there's no file API yet), but they open up the sandbox -- so registered here.
+inventory::submit! {
+    PermissionInfo {
+        name        : "avdan.file.read",
+        description : "Read files (in the sandbox: the directories its pattern names).",
+    }
+}
+
+inventory::submit! {
+    PermissionInfo {
+        name        : "avdan.file.write",
+        description : "Write files (in the sandbox: the directories its pattern names).",
+    }
+}
+
+// Read-only, for V8, libc, TLS certificates, DNS...
+const SYSTEM_PATHS : [&str; 8] = ["/usr", "/lib", "/lib64", "/etc", "/proc", "/sys/devices/system/cpu", "/dev/urandom", "/dev/null"];
+
+// Denied to every sandboxed extension.
+const DENIED_SYSCALLS : [i64; 20] = [
+    libc::SYS_ptrace, libc::SYS_process_vm_readv, libc::SYS_process_vm_writev,
+    libc::SYS_mount, libc::SYS_umount2, libc::SYS_pivot_root, libc::SYS_chroot,
+    libc::SYS_init_module, libc::SYS_finit_module, libc::SYS_delete_module,
+    libc::SYS_kexec_load, libc::SYS_reboot, libc::SYS_swapon, libc::SYS_swapoff,
+    libc::SYS_bpf, libc::SYS_perf_event_open, libc::SYS_keyctl, libc::SYS_userfaultfd,
+    libc::SYS_unshare, libc::SYS_setns,
+];
+
+// `clone` flags which would put the child in new namespaces (where Landlock and seccomp still apply,
+// but e.g. a new user namespace hands out capabilities).
+const NAMESPACE_FLAGS : [i32; 7] = [
+    libc::CLONE_NEWNS, libc::CLONE_NEWUSER, libc::CLONE_NEWPID, libc::CLONE_NEWNET,
+    libc::CLONE_NEWUTS, libc::CLONE_NEWIPC, libc::CLONE_NEWCGROUP,
+];
+
+const SECRETS_PERMISSION : &str = "avdan.secrets";
+
+// The child's connection to its parent.
+static CHANNEL : Mutex<Option<BufReader<UnixStream>>> = Mutex::new(None);
+
+/// What a sandboxed extension asks its parent to do on its behalf.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    /// Append to the audit log.
+    Audit { entry: AuditEntry },
+    /// Load the extension's grants.
+    Grants,
+    /// Save the extension's grants -- the parent only ever *removes* grants this way.
+    SaveGrants { grants: Vec<Grant> },
+    /// Ask the parent's consent provider for a permission.
+    Consent { permission: String },
+    /// Get, set or delete one of the extension's secrets.
+    Secret { action: SecretAction },
+    /// The extension was reloaded after a change -- the parent loads (and verifies) it again too.
+    Reload,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Done,
+    Error { message: String },
+    Consent { allow: bool, expires_in: Option<u64> },
+    Grants { grants: Vec<Grant> },
+    Secret { value: Option<String> },
+}
+
+///
+/// ## Sandbox
+///
+/// `--sandbox` runs the extension in a child process, restricted by
+/// * Landlock -- it can only read the extension's folder, system libraries and
+///   whatever its scoped file permissions and command declarations need.
+/// * seccomp -- no debugging, mounting, kernel modules, namespaces...; no `execve` without
+///   `avdan.shell.exec`, no internet sockets without an `avdan.net` permission, and no Unix
+///   or netlink sockets at all (the session bus would be a way out).
+///
+/// Both are derived from the extension's [`Constraints`]. The child can't write the audit log,
+/// its grants or ask the user itself, so it sends [`Request`]s to the parent over a socket.
+///
+
+pub struct Sandbox {}
+
+impl Sandbox {
+    /// Picks up the parent's socket, if this process is a sandboxed child.
+    pub fn connect() -> () {
+        if let Some(fd) = env::var(CHANNEL_ENV).ok().and_then(|fd| fd.parse::<i32>().ok()) {
+            env::remove_var(CHANNEL_ENV);
+
+            let stream = unsafe { UnixStream::from_raw_fd(fd) };
+            *CHANNEL.lock().unwrap() = Some(BufReader::new(stream));
+        }
+    }
+
+    pub fn is_child() -> bool {
+        CHANNEL.lock().unwrap().is_some()
+    }
+
+    /// Sends a request to the parent, and waits for its response.
+    pub fn request(request: &Request) -> Result<Response, String> {
+        let mut channel = CHANNEL.lock().unwrap();
+        let channel = channel.as_mut().ok_or("Not sandboxed!")?;
+
+        let mut line = serde_json::to_string(request).unwrap();
+        line.push('\n');
+
+        channel.get_mut().write_all(line.as_bytes()).map_err(|e| e.to_string())?;
+
+        let mut response = String::new();
+        channel.read_line(&mut response).map_err(|e| e.to_string())?;
+
+        match serde_json::from_str(&response).map_err(|e| e.to_string())? {
+            Response::Error { message } => Err(message),
+            response                    => Ok(response),
+        }
+    }
+
+    /*
+     *  Parent
+     */
+
+    /// Runs the extension in a sandboxed child process (with the same arguments, bar `--sandbox`),
+    /// serving its requests until it exits. Returns the child's exit code.
+    pub fn run(args: &[String], extension: &Extension, flags: &Flags) -> i32 {
+        let (parent, child) = UnixStream::pair().expect("Could not create the sandbox's socket!");
+
+        let child_fd = child.as_raw_fd();
+        let mut command = Command::new(env::current_exe().expect("Could not find the runtime's executable!"));
+
+        command
+            .args(args[1..].iter().filter(|a| *a != "--sandbox"))
+            .env(CHANNEL_ENV, CHANNEL_FD.to_string());
+
+        unsafe {
+            command.pre_exec(move || {
+                // dup2 clears close-on-exec, unless the fd is already the one we want.
+                let res = match child_fd == CHANNEL_FD {
+                    true  => libc::fcntl(CHANNEL_FD, libc::F_SETFD, 0),
+                    false => libc::dup2(child_fd, CHANNEL_FD),
+                };
+
+                match res {
+                    -1 => Err(io::Error::last_os_error()),
+                    _  => Ok(()),
+                }
+            });
+        }
+
+        let mut process = command.spawn().expect("Could not start the sandboxed extension!");
+        drop(child);
+
+        let mut reader = BufReader::new(parent.try_clone().unwrap());
+        let mut writer = parent;
+        let mut line = String::new();
+
+        // The extension as it is on disk now (after `--watch` reloads).
+        let mut reloaded : Option<Extension> = None;
+
+        while reader.read_line(&mut line).map(|n| n > 0).unwrap_or(false) {
+            let response = match serde_json::from_str::<Request>(&line) {
+                Ok(Request::Reload) => match Self::reload(&args[1], flags) {
+                    Ok(e)       => {
+                        reloaded = Some(e);
+                        Response::Done
+                    },
+                    Err(message) => Response::Error { message },
+                },
+                Ok(request) => Self::serve(reloaded.as_ref().unwrap_or(extension), flags, request),
+                Err(err)    => Response::Error { message: err.to_string() },
+            };
+
+            let mut out = serde_json::to_string(&response).unwrap();
+            out.push('\n');
+
+            if writer.write_all(out.as_bytes()).is_err() {
+                break;
+            }
+
+            line.clear();
+        }
+
+        process.wait().ok().and_then(|s| s.code()).unwrap_or(1)
+    }
+
+    /// Loads the extension again for a reloaded child, checking its signature as the child should have.
+    fn reload(path: &str, flags: &Flags) -> Result<Extension, String> {
+        let extension = Extension::try_load(path, &flags.overrides)?;
+
+        if flags.require_signed && !extension.verification().is_verified() {
+            return Err("Refusing to run an extension which is not signed by a trusted publisher (--require-signed).".to_string());
+        }
+
+        Ok(extension)
+    }
+
+    fn serve(extension: &Extension, flags: &Flags, request: Request) -> Response {
+        let result = match request {
+            // The parent knows which extension it's running, whatever the child says.
+            Request::Audit { mut entry } => {
+                entry.extension = extension.name().clone();
+                entry.identity  = Some(extension.identity());
+                AuditLog::append(&entry).map(|_| Response::Done).map_err(|e| e.to_string())
+            },
+
+            Request::Grants => Ok(Response::Grants { grants: Grants::load(&extension.identity()).list().clone() }),
+
+            Request::SaveGrants { grants } => {
+                let mut saved = Grants::load(&extension.identity());
+                let revoked : Vec<String> = saved.list().iter()
+                    .filter(|g| !grants.iter().any(|k| k.permission == g.permission))
+                    .map(|g| g.permission.clone())
+                    .collect();
+
+                for permission in revoked {
+                    saved.revoke(&permission);
+                }
+
+                saved.save().map(|_| Response::Done).map_err(|e| e.to_string())
+            },
+
+            Request::Consent { permission } => {
+                let permitted = Grant::validate_request(&permission).is_ok()
+                    && extension.security().is_within_policy(Permission::new(&permission).name());
+
+                match permitted {
+                    false => Ok(Response::Consent { allow: false, expires_in: None }),
+                    true  => match flags.consent.request(&extension.identity(), &permission) {
+                        Consent::Deny           => Ok(Response::Consent { allow: false, expires_in: None }),
+                        Consent::Allow(expires) => {
+                            let mut grants = Grants::load(&extension.identity());
+                            grants.add(Grant::new(&permission, expires));
+
+                            grants.save()
+                                .map(|_| Response::Consent { allow: true, expires_in: expires.map(|e| e.as_secs()) })
+                                .map_err(|e| e.to_string())
+                        }
+                    },
+                }
+            },
+
+            Request::Reload => Err("Unexpected reload request!".to_string()),
+
+            // Checked again here, as the child could be lying about its permissions.
+            Request::Secret { action } => {
+                let permitted = extension.security().has_permission(SECRETS_PERMISSION)
+                    || (extension.security().is_within_policy(SECRETS_PERMISSION) && Grants::load(&extension.identity()).has(SECRETS_PERMISSION));
+
+                match permitted {
+                    false => Err(format!("SecurityException -- Your extension does not have '{}'.", SECRETS_PERMISSION)),
+                    true  => Secrets::run(&extension.identity(), action).map(|value| Response::Secret { value }),
+                }
+            },
+        };
+
+        result.unwrap_or_else(|message| Response::Error { message })
+    }
+
+    /*
+     *  Child
+     */
+
+    /// The directory part of a path pattern, before any glob (`~/Documents/**/*.txt` -> `~/Documents`).
+    fn literal_prefix(pattern: &str) -> PathBuf {
+        PathBuf::from(expand_home(pattern)).components()
+            .take_while(|c| !c.as_os_str().to_str().unwrap_or("*").contains(&['*', '?', '[', '{'][..]))
+            .collect()
+    }
+
+    /// The paths an extension may touch -- path -> access.
+    fn paths(root: &Path, constraints: &Constraints) -> BTreeMap<PathBuf, BitFlags<AccessFs>> {
+        let abi   = ABI::V1;
+        let read  = make_bitflags!(AccessFs::{ReadFile | ReadDir});
+        let exec  = AccessFs::from_read(abi);
+        let all   = AccessFs::from_all(abi);
+        let shell = constraints.has_permission("avdan.shell.exec") && !constraints.commands().is_empty();
+
+        let mut paths : BTreeMap<PathBuf, BitFlags<AccessFs>> = BTreeMap::new();
+        let mut allow = |path: PathBuf, access: BitFlags<AccessFs>| {
+            *paths.entry(path).or_insert(BitFlags::EMPTY) |= access;
+        };
+
+        allow(root.canonicalize().unwrap_or(root.to_path_buf()), read);
+
+        // Commands need the dynamic loader (and their interpreters) executable.
+        for p in SYSTEM_PATHS {
+            allow(PathBuf::from(p), match shell { true => exec, false => read });
+        }
+
+        // The keyring and policy are read in here. Grants (and the audit log) are every extension's,
+        // so those go through the parent instead. (Secrets are kept out of both.)
+        if let Some(dir) = dirs::config_dir() {
+            allow(dir.join("avdan-js"), read);
+        }
+
+        if shell {
+            for c in constraints.commands() {
+                if let Ok(path) = which(&c.name) {
+                    allow(path, exec);
+                }
+
+                for cwd in c.cwd.iter().flatten() {
+                    allow(Self::literal_prefix(cwd), all);
+                }
+            }
+        }
+
+        for p in constraints.permissions().iter().filter_map(|p| p.split_once(":")) {
+            match p.0 {
+                "avdan.file.read"  => allow(Self::literal_prefix(p.1), read),
+                "avdan.file.write" => allow(Self::literal_prefix(p.1), all),
+                _                  => {},
+            }
+        }
+
+        // Not every system has them all (e.g. no `/lib64`), and Landlock can't add a rule for a missing path.
+        paths.retain(|path, _| path.exists());
+        paths
+    }
+
+    fn landlock(root: &Path, constraints: &Constraints) -> Result<RulesetStatus, String> {
+        let abi = ABI::V1;
+
+        let mut ruleset = Ruleset::new()
+            .handle_access(AccessFs::from_all(abi)).map_err(|e| e.to_string())?
+            .create().map_err(|e| e.to_string())?;
+
+        for (path, access) in Self::paths(root, constraints) {
+            ruleset = ruleset.add_rules(path_beneath_rules([path], access)).map_err(|e| e.to_string())?;
+        }
+
+        let status = ruleset.restrict_self().map_err(|e| e.to_string())?;
+
+        Ok(status.ruleset)
+    }
+
+    fn install(rules: BTreeMap<i64, Vec<SeccompRule>>, action: SeccompAction) -> Result<(), String> {
+        let filter = SeccompFilter::new(
+            rules,
+            SeccompAction::Allow,
+            action,
+            env::consts::ARCH.try_into().map_err(|e: seccompiler::BackendError| e.to_string())?,
+        ).map_err(|e| e.to_string())?;
+
+        let program : BpfProgram = filter.try_into().map_err(|e: seccompiler::BackendError| e.to_string())?;
+        seccompiler::apply_filter(&program).map_err(|e| e.to_string())
+    }
+
+    fn seccomp(constraints: &Constraints) -> Result<(), String> {
+        let mut rules : BTreeMap<i64, Vec<SeccompRule>> = DENIED_SYSCALLS.iter().map(|s| (*s, vec![])).collect();
+
+        // Threads are fine, new namespaces aren't (same as `unshare`).
+        let namespace = NAMESPACE_FLAGS.iter().map(|f| {
+            let condition = SeccompCondition::new(0, SeccompCmpArgLen::Qword, SeccompCmpOp::MaskedEq(*f as u64), *f as u64).map_err(|e| e.to_string())?;
+            SeccompRule::new(vec![condition]).map_err(|e| e.to_string())
+        }).collect::<Result<Vec<_>, String>>()?;
+
+        rules.insert(libc::SYS_clone, namespace);
+
+        if !constraints.has_permission("avdan.shell.exec") || constraints.commands().is_empty() {
+            rules.insert(libc::SYS_execve, vec![]);
+            rules.insert(libc::SYS_execveat, vec![]);
+        }
+
+        let family = |f: i32| -> Result<SeccompRule, String> {
+            let condition = SeccompCondition::new(0, SeccompCmpArgLen::Dword, SeccompCmpOp::Eq, f as u64).map_err(|e| e.to_string())?;
+            SeccompRule::new(vec![condition]).map_err(|e| e.to_string())
+        };
+
+        // Landlock (v1) doesn't cover connecting to Unix sockets, and the session bus can start
+        // processes outside the sandbox. The parent's socket is inherited, so none is ever needed.
+        let mut sockets = vec![family(libc::AF_UNIX)?, family(libc::AF_NETLINK)?];
+
+        if !constraints.has_any_permission("avdan.net") {
+            sockets.extend([family(libc::AF_INET)?, family(libc::AF_INET6)?]);
+        }
+
+        rules.insert(libc::SYS_socket, sockets);
+
+        Self::install(rules, SeccompAction::Errno(libc::EPERM as u32))?;
+
+        // `clone3` takes its flags in a struct seccomp can't look into. "Not implemented"
+        // makes libc fall back to `clone` (filtered above), where EPERM would fail every new thread.
+        Self::install(BTreeMap::from([(libc::SYS_clone3, vec![])]), SeccompAction::Errno(libc::ENOSYS as u32))
+    }
+
+    /// Restricts this (child) process -- before any other thread is started, as both are inherited.
+    pub fn restrict(root: &Path, constraints: &Constraints) -> Result<(), String> {
+        match Self::landlock(root, constraints)? {
+            RulesetStatus::FullyEnforced => {},
+            _ => println!("{} Landlock is not (fully) supported by this kernel, the filesystem is not restricted!", "[sandbox]".yellow()),
+        }
+
+        Self::seccomp(constraints)
+    }
+}
+
+/// Finds a program on `PATH` (or checks a path).
+fn which(command: &str) -> Result<PathBuf, ()> {
+    if command.contains("/") {
+        return Ok(PathBuf::from(command));
+    }
+
+    env::var_os("PATH")
+        .and_then(|paths| env::split_paths(&paths).map(|d| d.join(command)).find(|p| p.is_file()))
+        .ok_or(())
+}
+
+///
+/// ## BrokerConsent
+///
+/// A sandboxed extension's consent provider -- asks the parent's.
+///
+
+pub struct BrokerConsent {}
+
+impl ConsentProvider for BrokerConsent {
+    fn request(&self, _extension: &str, permission: &str) -> Consent {
+        match Sandbox::request(&Request::Consent { permission: permission.to_string() }) {
+            Ok(Response::Consent { allow: true, expires_in }) => Consent::Allow(expires_in.map(Duration::from_secs)),
+            _                                                 => Consent::Deny,
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::{collections::HashMap, path::{PathBuf, Path}};
+
+use sourcemap::SourceMap;
+use v8::{CallbackScope, Context, HandleScope, Local, Object, Function, Value};
+
+use crate::{core::obj_get_property, Avdan::loader::Package};
+
+const INLINE_PREFIX : &str = "data:application/json;base64,";
+
+///
+/// ## SourceMaps
+///
+/// Keeps the source maps of every loaded script (keyed by its script origin name),
+/// and remaps `Error.stack` traces back to the original (e.g. TypeScript or bundled) sources.
+///
+
+pub struct SourceMaps {
+    maps : HashMap<String, SourceMap>,
+}
+
+/// A position in an original source file.
+pub struct Mapped {
+    pub source : String,
+    pub line   : u32,
+    pub column : u32,
+    pub name   : Option<String>,
+}
+
+impl SourceMaps {
+    pub fn into_scope<'a>(scope: &mut HandleScope<'a>) -> () {
+        scope.set_slot (
+            SourceMaps {
+                maps : HashMap::new()
+            }
+        );
+
+        // On the isolate, rather than `Error.prepareStackTrace`, so no extension can replace it.
+        scope.set_prepare_stack_trace_callback(Self::prepare_stack_trace_callback);
+    }
+
+    /// Finds the `//# sourceMappingURL=...` comment of a script, if it has one.
+    pub fn source_mapping_url(source: &str) -> Option<String> {
+        source.lines().rev()
+            .map(|l| l.trim())
+            .find(|l| l.starts_with("//# sourceMappingURL=") || l.starts_with("//@ sourceMappingURL="))
+            .map(|l| l[21..].trim().to_string())
+    }
+
+    /// Loads a source map from its URL -- either inline (`data:` URL) or relative to the script.
+    fn load(path: &Path, url: &str) -> Option<SourceMap> {
+        let bytes = match url.strip_prefix(INLINE_PREFIX) {
+            Some(data) => base64::decode(data).ok()?,
+            None       => {
+                let file = url.strip_prefix("file://").unwrap_or(url);
+                Package::read(&path.parent()?.join(file)).ok()?
+            }
+        };
+
+        SourceMap::from_slice(&bytes).ok()
+    }
+
+    /// Parses the source map of a script (if it has one), and remembers it under `name`.
+    ///
+    /// Returns the source map URL, to be put in the script's origin.
+    pub fn register<'a>(scope: &mut HandleScope<'a>, name: &str, path: &PathBuf, source: &str) -> String {
+        let url = match Self::source_mapping_url(source) {
+            Some(url) => url,
+            None      => return "".to_string(),
+        };
+
+        if let Some(map) = Self::load(path, &url) {
+            if let Some(maps) = scope.get_slot_mut::<SourceMaps>() {
+                maps.maps.insert(name.to_string(), map);
+            }
+        }
+
+        // Don't hand huge inline maps over to V8, it only needs to know there is one.
+        match url.starts_with("data:") {
+            true  => path.to_str().unwrap().to_string() + ".map",
+            false => url,
+        }
+    }
+
+    /// Remaps a (1-based) line and column of a loaded script.
+    pub fn remap(&self, name: &str, line: u32, column: u32) -> Option<Mapped> {
+        let map = self.maps.get(name)?;
+        let token = map.lookup_token(line.checked_sub(1)?, column.saturating_sub(1))?;
+
+        Some(Mapped {
+            source : token.get_source().unwrap_or(name).to_string(),
+            line   : token.get_src_line() + 1,
+            column : token.get_src_col() + 1,
+            name   : token.get_name().map(|n| n.to_string()),
+        })
+    }
+
+    /// The original source text of a mapped file (e.g. the TypeScript inlined in its source map).
+    pub fn source_contents(&self, name: &str, source: &str) -> Option<&str> {
+        let map = self.maps.get(name)?;
+        let index = map.sources().position(|s| s == source)?;
+
+        map.get_source_contents(index as u32)
+    }
+
+    fn call_method<'a>(scope: &mut HandleScope<'a>, obj: Local<Object>, name: &str) -> Local<'a, Value> {
+        let f : Local<Function> = obj_get_property(scope, obj, name).try_into().unwrap();
+        f.call(scope, obj.into(), &[]).unwrap()
+    }
+
+    /// Formats a single V8 `CallSite` as a stack frame, remapped when possible.
+    fn format_call_site<'a>(scope: &mut HandleScope<'a>, site: Local<Object>) -> String {
+        let function = Self::call_method(scope, site, "getFunctionName");
+        let file     = Self::call_method(scope, site, "getFileName");
+        let line     = Self::call_method(scope, site, "getLineNumber");
+        let column   = Self::call_method(scope, site, "getColumnNumber");
+
+        let mut function = match function.is_null_or_undefined() {
+            true  => "<anonymous>".to_string(),
+            false => function.to_rust_string_lossy(scope),
+        };
+
+        if file.is_null_or_undefined() {
+            return format!("    at {} (native)", function);
+        }
+
+        let mut file   = file.to_rust_string_lossy(scope);
+        let mut line   = line.uint32_value(scope).unwrap_or(0);
+        let mut column = column.uint32_value(scope).unwrap_or(0);
+
+        if let Some(mapped) = scope.get_slot::<SourceMaps>().and_then(|m| m.remap(&file, line, column)) {
+            file   = mapped.source;
+            line   = mapped.line;
+            column = mapped.column;
+
+            if let Some(name) = mapped.name {
+                function = name;
+            }
+        }
+
+        format!("    at {} ({}:{}:{})", function, file, line, column)
+    }
+
+    /// V8 calls this to format `error.stack`, with the error and its `CallSite`s.
+    extern "C" fn prepare_stack_trace_callback<'s> (
+        context : Local<'s, Context>,
+        error   : Local<'s, Value>,
+        sites   : Local<'s, v8::Array>
+    ) -> *const Value {
+        let scope = &mut unsafe {
+            CallbackScope::new(context)
+        };
+
+        let mut lines = vec![error.to_rust_string_lossy(scope)];
+
+        for site in crate::Avdan::utils::array_to_vec::<Object>(scope, sites) {
+            lines.push(Self::format_call_site(scope, site));
+        }
+
+        let stack : Local<Value> = v8::String::new(scope, lines.join("\n").as_str()).unwrap().into();
+        &*stack as *const Value
+    }
+
+    /// The best description of a thrown value --
+    /// its (remapped) stack for errors, or its string value otherwise.
+    pub fn describe<'a>(scope: &mut HandleScope<'a>, value: Local<Value>) -> String {
+        if value.is_native_error() {
+            let obj : Local<Object> = value.try_into().unwrap();
+            let stack = obj_get_property(scope, obj, "stack");
+
+            if stack.is_string() {
+                return stack.to_rust_string_lossy(scope);
+            }
+        }
+
+        value.to_rust_string_lossy(scope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::SourceMaps;
+    use crate::Avdan::runtime::avmod::{testing::with_scope, AvModTS};
+
+    #[test]
+    fn source_mapping_urls() {
+        assert_eq!(SourceMaps::source_mapping_url("a();\n//# sourceMappingURL=a.js.map\n").as_deref(), Some("a.js.map"));
+        assert_eq!(SourceMaps::source_mapping_url("//# sourceMappingURL=old.map\na();\n//@ sourceMappingURL=new.map").as_deref(), Some("new.map"));
+        assert_eq!(SourceMaps::source_mapping_url("a();\n"), None);
+    }
+
+    #[test]
+    fn transpiled_typescript_is_remapped() {
+        let path = PathBuf::from("/ext/main.ts");
+        let ts = "interface Point {\n  x: number;\n}\n\nexport function fail(p: Point): never {\n  throw new Error(`${p.x}`);\n}\n";
+        let js = AvModTS::transpile(&path, ts.to_string()).ok().unwrap();
+
+        // Where the `throw` ended up in the emitted JavaScript.
+        let (line, text) = js.lines().enumerate().find(|(_, l)| l.contains("throw")).unwrap();
+        let column = text.find("throw").unwrap() as u32 + 1;
+
+        with_scope(vec![], |scope| {
+            SourceMaps::register(scope, "/ext/main.ts", &path, &js);
+
+            let maps = scope.get_slot::<SourceMaps>().unwrap();
+            let mapped = maps.remap("/ext/main.ts", line as u32 + 1, column).unwrap();
+
+            assert_eq!(mapped.line, 6);
+            assert_eq!(mapped.column, 3);
+            assert_eq!(maps.source_contents("/ext/main.ts", &mapped.source), Some(ts));
+        });
+    }
+
+    #[test]
+    fn scripts_without_maps_are_not_remapped() {
+        with_scope(vec![], |scope| {
+            let url = SourceMaps::register(scope, "/ext/main.js", &PathBuf::from("/ext/main.js"), "a();\n");
+
+            assert_eq!(url, "");
+            assert!(scope.get_slot::<SourceMaps>().unwrap().remap("/ext/main.js", 1, 1).is_none());
+        });
+    }
+}
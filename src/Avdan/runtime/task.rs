@@ -3,11 +3,12 @@
 // Cancellable
 
 use std::{any::TypeId, thread, sync::mpsc::Sender};
-use v8::{HandleScope, PromiseResolver, Global, Local, Value, Uint8Array, ArrayBuffer, Object, Exception, Function};
+use v8::{HandleScope, PromiseResolver, Global, Local, Value, Uint8Array, ArrayBuffer, Object, Exception, Function, Private};
 use crate::core::{def_safe_function, def_safe_property};
 use super::{Runtime, message::{Message, Type as MessageType}, PromIndex, Builder};
 
-const AUX_HANDLERS : &str = "___aux___";
+// A private symbol, so the handlers can't be reached (or replaced) from JS.
+const AUX_HANDLERS : &str = "avdan.task.handlers";
 
 pub struct Task {}
 
@@ -30,17 +31,30 @@ impl Task {
                 f((task_id, tx_client)), builder
             ).message(task_id);
             
-            tx.send(output).expect(format!("[📋 TASK {}] Failed to send its output.", task_id).as_str());
+            // The runtime may have moved on (e.g. a `--watch` reload) -- nobody's waiting for this any more.
+            let _ = tx.send(output);
         });
 
         prom
     }
 
+    fn handlers_key<'a>(scope: &mut HandleScope<'a>) -> Local<'a, Private> {
+        let name = v8::String::new(scope, AUX_HANDLERS).unwrap();
+        Private::for_api(scope, Some(name))
+    }
+
+    fn handlers<'a>(scope: &mut HandleScope<'a>, obj: Local<Object>) -> Option<Local<'a, Object>> {
+        let key = Self::handlers_key(scope);
+
+        obj.get_private(scope, key)?.try_into().ok()
+    }
+
     // Add Task.on(event, hander)
     pub fn assign_auxiliary_funcs<'a>(scope: &mut HandleScope<'a>, obj : Local<'a, Object>) -> () {
         let blank_aux_handlers = Object::new(scope);
-        
-        def_safe_property(scope, obj, AUX_HANDLERS, blank_aux_handlers.into());
+        let key = Self::handlers_key(scope);
+
+        obj.set_private(scope, key, blank_aux_handlers.into());
         def_safe_function!(scope, obj, "on", Self::on_callback);
     }
 
@@ -69,22 +83,24 @@ impl Task {
 
         let this = args.this();
 
-        let handlers_key = v8::String::new(scope, AUX_HANDLERS).unwrap(); 
-        let handlers : Local<Object> =
-            this.get(scope, handlers_key.into()).expect("Task Handlers internal object is undefined !")
-            .try_into().expect("Couldn't cast internal handlers to object!");
+        let handlers = match Self::handlers(scope, this) {
+            Some(h) => h,
+            None    => {
+                let msg = v8::String::new(scope, "Task.on called on something which is not a Task.").unwrap();
+                let excp = v8::Exception::type_error(scope, msg);
+
+                scope.throw_exception(excp.into());
+                return;
+            }
+        };
 
-        let event_key = v8::String::new(scope, event.as_str()).unwrap();
         def_safe_property(scope, handlers, event.as_str(), callback.into());
 
         rv.set(this.into());
     }
 
-    pub fn get_auxiliary_func<'a>(scope : &mut HandleScope<'a>, prom : &PromiseResolver, k : String) -> Option<Local<'a, Function>> {
-        let aux_key = v8::String::new(scope, AUX_HANDLERS).unwrap();
-        let handlers : Local<Object> = prom.get(scope, aux_key.into())
-                .expect("Task should have handlers!")
-                .try_into().expect("Auxiliary Handlers should be in an object!");
+    pub fn get_auxiliary_func<'a>(scope : &mut HandleScope<'a>, prom : Local<PromiseResolver>, k : String) -> Option<Local<'a, Function>> {
+        let handlers = Self::handlers(scope, prom.into()).expect("Task should have handlers!");
     
         let event_name = v8::String::new(scope, k.as_str()).unwrap(); 
         match handlers.get(scope, event_name.into()) {
@@ -0,0 +1,171 @@
+use std::{collections::HashMap, path::{PathBuf, Path}, io, fs, thread, time::Duration};
+
+use colored::Colorize;
+use inotify::{Inotify, WatchMask, WatchDescriptor, EventMask};
+
+use crate::Avdan::loader::MANIFEST_FILE;
+
+// Editors tend to save in bursts of events (write, chmod, rename, ...),
+// give them a moment to settle before reloading.
+const DEBOUNCE : Duration = Duration::from_millis(50);
+
+///
+/// ## Watcher
+///
+/// Watches an extension's directory (recursively) for `--watch` --
+/// or, for a package (see [`crate::Avdan::loader::Package`]), the package file.
+///
+
+pub struct Watcher {
+    inotify : Inotify,
+    dirs    : HashMap<WatchDescriptor, PathBuf>,
+    root    : PathBuf,
+    package : bool,
+}
+
+/// Files which changed since the extension was last (re)started.
+pub struct Changes {
+    files    : Vec<PathBuf>,
+    manifest : bool,
+}
+
+impl Watcher {
+    pub fn new(root: &str) -> io::Result<Watcher> {
+        let mut w = Watcher {
+            inotify : Inotify::init()?,
+            dirs    : HashMap::new(),
+            root    : Path::new(root).canonicalize()?,
+            package : Path::new(root).is_file(),
+        };
+
+        match w.package {
+            // Its folder, as the package is usually replaced rather than written to.
+            true  => w.add_watch(&w.root.parent().unwrap().to_path_buf())?,
+            false => w.watch_dir(&w.root.clone())?,
+        }
+
+        Ok(w)
+    }
+
+    fn add_watch(&mut self, dir: &PathBuf) -> io::Result<()> {
+        let mask = WatchMask::CLOSE_WRITE | WatchMask::MODIFY | WatchMask::CREATE
+                 | WatchMask::DELETE | WatchMask::MOVED_TO | WatchMask::MOVED_FROM;
+
+        let wd = self.inotify.add_watch(dir, mask)?;
+        self.dirs.insert(wd, dir.clone());
+
+        Ok(())
+    }
+
+    fn watch_dir(&mut self, dir: &PathBuf) -> io::Result<()> {
+        self.add_watch(dir)?;
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let hidden = path.file_name()
+                .map(|n| n.to_string_lossy().starts_with("."))
+                .unwrap_or(false);
+
+            if path.is_dir() && !hidden {
+                self.watch_dir(&path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read(&mut self, blocking: bool) -> Vec<(PathBuf, bool)> {
+        let mut buffer = [0u8; 4096];
+
+        let events = match blocking {
+            true  => self.inotify.read_events_blocking(&mut buffer),
+            false => self.inotify.read_events(&mut buffer),
+        };
+
+        let events = match events {
+            Ok(events) => events,
+            Err(_)     => return vec![],
+        };
+
+        events.filter_map(|e| {
+            let dir = self.dirs.get(&e.wd)?;
+            Some((dir.join(e.name?), e.mask.contains(EventMask::ISDIR)))
+        })
+        .collect()
+    }
+
+    fn collect(&mut self, mut paths: Vec<(PathBuf, bool)>) -> Option<Changes> {
+        if paths.is_empty() {
+            return None;
+        }
+
+        thread::sleep(DEBOUNCE);
+        paths.extend(self.read(false));
+
+        let mut changes = Changes {
+            files    : vec![],
+            manifest : false,
+        };
+
+        // Only the package itself matters in its folder, and it's remounted as a whole.
+        if self.package {
+            paths.retain(|(path, _)| *path == self.root);
+
+            if paths.is_empty() {
+                return None;
+            }
+
+            changes.manifest = true;
+        }
+
+        for (path, is_dir) in paths {
+            if is_dir && path.exists() && !self.package {
+                let _ = self.watch_dir(&path);
+            }
+
+            if path == self.root.join(MANIFEST_FILE) {
+                changes.manifest = true;
+            }
+
+            if !is_dir && !changes.files.contains(&path) {
+                changes.files.push(path);
+            }
+        }
+
+        Some(changes)
+    }
+
+    /// Returns any pending changes, without blocking.
+    pub fn poll(&mut self) -> Option<Changes> {
+        let paths = self.read(false);
+        self.collect(paths)
+    }
+
+    /// Blocks until something changes.
+    pub fn wait(&mut self) -> Changes {
+        loop {
+            let paths = self.read(true);
+
+            if let Some(changes) = self.collect(paths) {
+                return changes;
+            }
+        }
+    }
+}
+
+impl Changes {
+    pub fn report(&self) -> () {
+        let action = match self.manifest {
+            true  => "Restarting extension",
+            false => "Reloading modules",
+        };
+
+        println!("\n{} {}", "[watch]".cyan(), action.bold());
+
+        for f in self.files.iter() {
+            println!("  {} {}", "changed".yellow(), f.to_str().unwrap());
+        }
+
+        println!();
+    }
+}
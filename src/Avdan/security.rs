@@ -1,12 +1,36 @@
-use std::{ffi::c_void, mem};
+use std::{collections::BTreeMap, path::Path, rc::Rc};
 use serde::{Serialize, Deserialize};
 use super::Permission;
 
+mod audit;
+mod codegen;
+mod command;
+mod consent;
+mod grants;
+mod overrides;
+mod policy;
+mod quota;
+mod registry;
+mod secrets;
+mod resource;
+
+pub use audit::{AuditLog, AuditEntry, AuditKind, format_timestamp};
+pub use codegen::CodeGeneration;
+pub use command::CommandDeclaration;
+pub use consent::{Consent, ConsentProvider, TerminalConsent, HostConsent, DenyConsent, provider as consent_provider};
+pub use grants::{Grant, Grants};
+pub use overrides::{Override, Overrides};
+pub use policy::{Policy, PolicyFile, Rules, Lists};
+pub use quota::{Quota, Reservation, Usage, quota_exception, QUOTA_EXCEEDED};
+pub use registry::{PermissionInfo, registry, validate_permission, validate_command};
+pub use secrets::{SecretAction, SecretKey, Secrets};
+pub use resource::{resource_matches, expand_home};
+
 ///
 /// ## Security::Constraints
 /// 
 /// Handles various security contraints of AvdanOS Search extensions, such as:
-/// * Shell command declararion.
+/// * Shell command declararion (see [`CommandDeclaration`]).
 /// * AvdanOS API access. 
 /// 
 ///
@@ -14,16 +38,30 @@ use super::Permission;
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Constraints {
     permissions : Vec<String>,
-    commands    : Vec<String>,
+    commands    : Vec<CommandDeclaration>,
+    /// Whether `eval`/`new Function` may be used (see [`CodeGeneration`]).
+    #[serde(default = "yes")]
+    eval        : bool,
+    /// Usage limits, keyed by permission (see [`Quota`]).
+    #[serde(default)]
+    quotas      : BTreeMap<String, Quota>,
+    /// The system/user policy's rules for this extension (see [`Policy`]), which also cap runtime grants.
+    #[serde(skip)]
+    policy      : Vec<Rules>,
 }
 
-const SECURITY_KEY : &str = "___SECURITY___";
+fn yes() -> bool {
+    true
+}
 
 impl Constraints {
     pub fn new<'a>(permissions : Vec<&'a str>, external_commands: Vec<&'a str>) -> Constraints {
         Constraints { 
             permissions : permissions.iter().map(|p| p.to_string()).collect(),
-            commands    : external_commands.iter().map(|p| p.to_string()).collect()
+            commands    : external_commands.iter().map(|c| CommandDeclaration::new(c)).collect(),
+            eval        : true,
+            quotas      : BTreeMap::new(),
+            policy      : vec![],
         }
     }
 
@@ -31,8 +69,31 @@ impl Constraints {
         return &self.permissions;
     }
 
+    // Whether code generation from strings is allowed.
+    pub fn allows_eval(&self) -> bool {
+        return self.eval;
+    }
+
+    /// Checks every declared permission (and quota key) against the registry (see [`PermissionInfo`]),
+    /// and that every declared command is well-formed.
+    pub fn validate(&self) -> Result<(), String> {
+        let errors : Vec<String> = self.permissions.iter().map(|p| validate_permission(p))
+            .chain(self.quotas.keys().map(|k| validate_permission(k)))
+            .chain(self.commands.iter().map(|c| validate_command(&c.name)))
+            .filter_map(|r| r.err())
+            .collect();
+
+        match errors.is_empty() {
+            true  => Ok(()),
+            false => Err(errors.join("\n")),
+        }
+    }
+
     pub fn throw_permission_exception<'a>(&self, scope: &mut v8::HandleScope<'a>, perm: &str) -> bool {
-        if !self.has_permission(perm) {
+        let allowed = self.allows(scope, perm);
+        AuditLog::record(scope, AuditKind::Permission, perm, None, allowed);
+
+        if !allowed {
             let e = v8::String::new(scope, format!("SecurityException -- Invalid permissions!\nYour extension does not have '{}'.", perm).as_str()).unwrap();
             let err = v8::Exception::error(scope, e);
             scope.throw_exception(err);
@@ -40,30 +101,137 @@ impl Constraints {
             return false;
         }
         
-        return true;
+        return self.throw_quota_exception(scope, perm);
     }   
 
-    pub fn throw_command_exception<'a>(&self, scope : &mut v8::HandleScope<'a>, cmd: &str) -> bool {
-        if !self.is_command_permitted(cmd) {
-            let e = v8::String::new(scope, format!("SecurityException -- Invalid command declaration!\nYour extension has not declared the use of `{}`.", cmd).as_str()).unwrap();
+    /// Like [`Constraints::throw_permission_exception`], for an API call on a concrete resource
+    /// (checked against scoped permissions, e.g. `avdan.net.fetch:https://api.github.com/*`).
+    pub fn throw_resource_exception<'a>(&self, scope: &mut v8::HandleScope<'a>, perm: &str, resource: &str) -> bool {
+        let allowed = self.allows_resource(scope, perm, resource);
+        AuditLog::record(scope, AuditKind::Permission, perm, Some(resource), allowed);
+
+        if !allowed {
+            let e = v8::String::new(scope, format!("SecurityException -- Invalid permissions!\nYour extension does not have '{}' for `{}`.", perm, resource).as_str()).unwrap();
             let err = v8::Exception::error(scope, e);
             scope.throw_exception(err);
             
             return false;
         }
         
+        return self.throw_quota_exception(scope, perm);
+    }
+
+    /// Counts a call of a permission against its quotas, throwing `QuotaExceeded` if one is used up.
+    pub fn throw_quota_exception<'a>(&self, scope: &mut v8::HandleScope<'a>, perm: &str) -> bool {
+        let usage = Usage::from_scope(scope);
+        let result = usage.call(self.quotas_for(perm));
+
+        if let Err(reason) = result {
+            AuditLog::record(scope, AuditKind::Quota, perm, None, false);
+
+            let err = quota_exception(scope, format!("{} -- {}", QUOTA_EXCEEDED, reason).as_str());
+            scope.throw_exception(err);
+
+            return false;
+        }
+
         return true;
     }
 
-    // Check to see if the  
+    // The quotas which apply to a permission (`avdan.net.*` applies to `avdan.net.fetch`),
+    // the manifest's and the policies' alike.
+    pub fn quotas_for<'s>(&'s self, perm: &'s str) -> impl Iterator<Item = (&'s String, &'s Quota)> {
+        self.quotas.iter()
+            .chain(self.policy.iter().flat_map(|r| r.quotas.iter()))
+            .filter(move |(key, _)| Permission::new(key).has(perm))
+    }
+
+    // Holds what's left of a permission's byte quotas for one transfer (`None` if there's no byte quota).
+    pub fn reserve_bytes<'a>(&self, scope: &mut v8::HandleScope<'a>, perm: &str) -> Option<Reservation> {
+        Usage::from_scope(scope).reserve_bytes(self.quotas_for(perm))
+    }
+
+    /// Checks a command invocation against the declarations,
+    /// returning the declaration it's allowed by (to spawn it with).
+    pub fn throw_command_exception<'a>(&self, scope : &mut v8::HandleScope<'a>, cmd: &str, args: &[String], cwd: &Path) -> Option<&CommandDeclaration> {
+        let result = self.check_command(cmd, args, cwd);
+        let invocation = format!("{} (in {})", args.join(" "), cwd.to_string_lossy());
+
+        AuditLog::record(scope, AuditKind::Command, cmd, Some(&invocation), result.is_ok());
+
+        match result {
+            Ok(declaration) => Some(declaration),
+            Err(reason)     => {
+                let e = v8::String::new(scope, format!("SecurityException -- Invalid command declaration!\n{}", reason).as_str()).unwrap();
+                let err = v8::Exception::error(scope, e);
+                scope.throw_exception(err);
+                
+                None
+            }
+        }
+    }
+
+    // Check to see if the extension has a permission, whatever it's used on.
+    // Scoped permissions (`name:pattern`) don't count here, see `has_permission_for`.
     pub fn has_permission(&self, perm: &str) -> bool {
         return self.permissions.iter().map(|a| a.as_str()).map(Permission::new).any(|p| {
-              p.has(perm)
+              !p.is_scoped() && p.has(perm)
         });
     }
 
+    // Check to see if the extension has a permission for a concrete resource.
+    pub fn has_permission_for(&self, perm: &str, resource: &str) -> bool {
+        return self.permissions.iter().map(|a| a.as_str()).map(Permission::new).any(|p| {
+              p.has_resource(perm, resource)
+        });
+    }
+
+    // Whether the system/user policy lets the extension have a permission at all.
+    pub fn is_within_policy(&self, perm: &str) -> bool {
+        self.policy.iter().all(|r| r.permits_permission(perm))
+    }
+
+    // Same as `allows`, for a concrete resource.
+    pub fn allows_resource<'a>(&self, scope: &mut v8::HandleScope<'a>, perm: &str, resource: &str) -> bool {
+        self.has_permission_for(perm, resource)
+            || (self.is_within_policy(perm) && Grants::from_scope(scope).map(|g| g.has_resource(perm, resource)).unwrap_or(false))
+    }
+
+    // A snapshot of `allows_resource` for one permission, which can be moved off the isolate's thread
+    // (e.g. to check where an HTTP redirect goes).
+    pub fn resource_checker<'a>(&self, scope: &mut v8::HandleScope<'a>, perm: &str) -> impl Fn(&str) -> bool + Send + Sync + 'static {
+        let granted : Vec<String> = match self.is_within_policy(perm) {
+            true  => Grants::from_scope(scope)
+                .map(|g| g.list().iter().filter(|g| !g.is_expired()).map(|g| g.permission.clone()).collect())
+                .unwrap_or_default(),
+            false => vec![],
+        };
+
+        let permissions : Vec<String> = self.permissions.iter().cloned().chain(granted).collect();
+        let perm = perm.to_string();
+
+        move |resource| permissions.iter().any(|p| Permission::new(p).has_resource(&perm, resource))
+    }
+
+    // Whether the extension has a permission,
+    // either from its manifest or granted at runtime (see [`Grants`]) -- if the policy permits it.
+    pub fn allows<'a>(&self, scope: &mut v8::HandleScope<'a>, perm: &str) -> bool {
+        self.has_permission(perm)
+            || (self.is_within_policy(perm) && Grants::from_scope(scope).map(|g| g.has(perm)).unwrap_or(false))
+    }
+
+    // Whether the extension has `namespace`, or any permission within it
+    // (e.g. `avdan.net.fetch` is within `avdan.net`).
+    pub fn has_any_permission(&self, namespace: &str) -> bool {
+        let ns = Permission::new(namespace);
+
+        self.permissions.iter().map(|a| a.as_str()).map(Permission::new).any(|p| {
+            p.has(namespace) || ns.has(p.name())
+        })
+    }
+
     // Return a list of all possible commands the extension can run.
-    pub fn commands(&self) -> &Vec<String> {
+    pub fn commands(&self) -> &Vec<CommandDeclaration> {
         return &self.commands;
     }
 
@@ -71,36 +239,35 @@ impl Constraints {
     //      when an extension is about to run a command
     //      to try to improve an extension's transparency.
     pub fn is_command_permitted(&self, command: &str) -> bool {
-        return self.commands.contains(&command.to_string());
+        return self.commands.iter().any(|c| c.name == command);
     }
 
-    /** STATIC FUNCTIONS */
+    // Checks a whole invocation (arguments & working directory) --
+    // a command can be declared several times, any matching declaration allows it.
+    pub fn check_command(&self, command: &str, args: &[String], cwd: &Path) -> Result<&CommandDeclaration, String> {
+        let mut reason = format!("Your extension has not declared the use of `{}`.", command);
 
-    pub fn into_scope<'a>(&self, scope: &mut v8::HandleScope<'a>) -> () {
-        let global = scope.get_current_context().global(scope);
+        for declaration in self.commands.iter().filter(|c| c.name == command) {
+            match declaration.check(args, cwd) {
+                Ok(_)  => return Ok(declaration),
+                Err(r) => reason = r,
+            }
+        }
 
-        let perms_unsafe = unsafe {
-            mem::transmute::<&Constraints, *mut c_void>(&self)
-        };
+        Err(reason)
+    }
 
-        let external_perms = v8::External::new(scope, perms_unsafe);
+    /** STATIC FUNCTIONS */
 
-        let key = v8::String::new(scope, SECURITY_KEY).unwrap();
-        
-        global.define_own_property(scope, key.into(), external_perms.into(), v8::READ_ONLY);
+    /// Gives the isolate its own copy, out of reach of JS.
+    pub fn into_scope<'a>(&self, scope: &mut v8::HandleScope<'a>) -> () {
+        scope.set_slot(Rc::new(self.clone()));
     }
 
-    pub fn from_scope<'a>(scope: &mut v8::HandleScope<'a>) -> &'a Constraints {
-        let global = scope.get_current_context().global(scope);
-        let s =  v8::String::new(scope, SECURITY_KEY).unwrap();
-        let val = global.get(scope, s.into()).expect("Expected the ___SECURITY___ variable!");
-        let v : v8::Local<v8::External> = unsafe {
-            mem::transmute::<v8::Local<v8::Value>, v8::Local<v8::External>>(val)
-        };
-
-        unsafe {
-            mem::transmute::<*mut c_void, &Constraints>(v.value())
-        }
+    pub fn from_scope<'a>(scope: &mut v8::HandleScope<'a>) -> Rc<Constraints> {
+        scope.get_slot::<Rc<Constraints>>()
+            .expect("The extension's constraints should be in the isolate!")
+            .clone()
     }
 }
 
@@ -0,0 +1,150 @@
+use std::{fs::{self, OpenOptions}, io::Write, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+
+use serde::{Serialize, Deserialize};
+use v8::HandleScope;
+
+use crate::Avdan::runtime::{SourceMaps, Sandbox, SandboxRequest};
+
+const AUDIT_FILE : &str = "avdan-js/audit.jsonl";
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditKind {
+    Permission,
+    Command,
+    /// `eval`, `new Function`... (the permission is `eval`, the resource the start of the source).
+    CodeGeneration,
+    /// A call over one of the permission's quotas.
+    Quota,
+    /// A command line override (the resource is the flag), see [`super::Overrides`].
+    Override,
+}
+
+///
+/// ## AuditEntry
+///
+/// One security decision, as a line of `~/.local/share/avdan-js/audit.jsonl`.
+///
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// UNIX timestamp (seconds).
+    pub timestamp  : u64,
+    pub extension  : String,
+    /// Who the extension is beyond its (self-chosen) name, see [`crate::Avdan::loader::Extension::identity`].
+    #[serde(default)]
+    pub identity   : Option<String>,
+    pub kind       : AuditKind,
+    /// The permission, or the command's name.
+    pub permission : String,
+    /// The resource an API was used on, or the command's arguments.
+    pub resource   : Option<String>,
+    pub allowed    : bool,
+    /// Where in the extension's JS the decision was made (`file:line:column`).
+    pub location   : Option<String>,
+}
+
+///
+/// ## AuditLog
+///
+/// Appends every decision of [`super::Constraints`] to the audit log.
+///
+
+pub struct AuditLog {
+    extension : String,
+    identity  : String,
+}
+
+impl AuditLog {
+    pub fn file() -> Option<PathBuf> {
+        dirs::data_dir().map(|d| d.join(AUDIT_FILE))
+    }
+
+    pub fn into_scope<'a>(extension: &str, identity: &str, scope: &mut HandleScope<'a>) -> () {
+        scope.set_slot(AuditLog {
+            extension : extension.to_string(),
+            identity  : identity.to_string(),
+        });
+    }
+
+    /// The (source-mapped) location of the innermost JS frame.
+    fn location<'a>(scope: &mut HandleScope<'a>) -> Option<String> {
+        let trace = v8::StackTrace::current_stack_trace(scope, 1)?;
+        let frame = trace.get_frame(scope, 0)?;
+
+        let file   = frame.get_script_name(scope)?.to_rust_string_lossy(scope);
+        let line   = frame.get_line_number() as u32;
+        let column = frame.get_column() as u32;
+
+        match scope.get_slot::<SourceMaps>().and_then(|m| m.remap(&file, line, column)) {
+            Some(m) => Some(format!("{}:{}:{}", m.source, m.line, m.column)),
+            None    => Some(format!("{}:{}:{}", file, line, column)),
+        }
+    }
+
+    pub fn record<'a>(scope: &mut HandleScope<'a>, kind: AuditKind, permission: &str, resource: Option<&str>, allowed: bool) -> () {
+        let (extension, identity) = match scope.get_slot::<AuditLog>() {
+            Some(log) => (log.extension.clone(), log.identity.clone()),
+            None      => return,
+        };
+
+        let entry = AuditEntry {
+            timestamp  : SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            extension,
+            identity   : Some(identity),
+            kind,
+            permission : permission.to_string(),
+            resource   : resource.map(|r| r.to_string()),
+            allowed,
+            location   : Self::location(scope),
+        };
+
+        if let Err(err) = Self::append(&entry) {
+            println!("Could not write to the audit log: {}", err);
+        }
+    }
+
+    pub fn append(entry: &AuditEntry) -> std::io::Result<()> {
+        // A sandboxed extension can't write the log, its parent does.
+        if Sandbox::is_child() {
+            return Sandbox::request(&SandboxRequest::Audit { entry: entry.clone() })
+                .map(|_| ())
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e));
+        }
+
+        let file = Self::file()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "No data directory!"))?;
+
+        fs::create_dir_all(file.parent().unwrap())?;
+
+        let mut f = OpenOptions::new().create(true).append(true).open(file)?;
+        writeln!(f, "{}", serde_json::to_string(entry).unwrap())
+    }
+
+    /// Every entry of the log, oldest first (unreadable lines are skipped).
+    pub fn read() -> Vec<AuditEntry> {
+        Self::file()
+            .and_then(|f| fs::read_to_string(f).ok())
+            .map(|text| text.lines().filter_map(|l| serde_json::from_str(l).ok()).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Formats a UNIX timestamp as `YYYY-MM-DD HH:MM:SS` (UTC).
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem  = secs % 86400;
+
+    // Days since the epoch to a civil date (Howard Hinnant's algorithm).
+    let z   = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp  = (5 * doy + 2) / 153;
+    let d   = doy - (153 * mp + 2) / 5 + 1;
+    let m   = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y   = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", y, m, d, rem / 3600, rem % 3600 / 60, rem % 60)
+}
@@ -0,0 +1,51 @@
+use v8::{CallbackScope, Context, HandleScope, Local, Value};
+
+use super::{AuditKind, AuditLog};
+
+// Audit entries keep the start of the offending source, not all of it.
+const SOURCE_PREVIEW : usize = 80;
+
+const MESSAGE : &str = "SecurityException -- Code generation from strings is disabled!\nYour extension's manifest (or the system policy) disallows `eval` and `new Function`.";
+
+///
+/// ## CodeGeneration
+///
+/// Disallows `eval`, `new Function(...)` and friends in a context, when the manifest
+/// (`"eval": false` in `security`) or the policy says so.
+///
+/// Every attempt is recorded in the audit log, and throws a `SecurityException`.
+///
+
+pub struct CodeGeneration {}
+
+impl CodeGeneration {
+    pub fn disallow<'a>(scope: &mut HandleScope<'a>) -> () {
+        let context = scope.get_current_context();
+        let message = v8::String::new(scope, MESSAGE).unwrap();
+
+        context.set_allow_generation_from_strings(false);
+        context.set_error_message_for_code_generation_from_strings(message);
+
+        scope.set_modify_code_generation_from_strings_callback(Self::modify_code_generation_callback);
+    }
+
+    /// V8 asks this before compiling any string, once the context disallows it.
+    pub extern "C" fn modify_code_generation_callback<'a> (
+        context       : Local<'a, Context>,
+        source        : Local<'a, Value>,
+        _is_code_like : bool,
+    ) -> v8::ModifyCodeGenerationFromStringsResult<'a> {
+        let scope = &mut unsafe {
+            CallbackScope::new(context)
+        };
+
+        let source : String = source.to_rust_string_lossy(scope).chars().take(SOURCE_PREVIEW).collect();
+        AuditLog::record(scope, AuditKind::CodeGeneration, "eval", Some(&source), false);
+
+        // Not allowed: V8 throws, with the message above.
+        v8::ModifyCodeGenerationFromStringsResult {
+            codegen_allowed : false,
+            modified_source : None,
+        }
+    }
+}
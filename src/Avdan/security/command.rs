@@ -0,0 +1,211 @@
+use std::{collections::BTreeMap, path::Path, process};
+
+use serde::{Serialize, Deserialize};
+
+use super::resource_matches;
+
+/// Environment variables kept from the runtime's own environment,
+/// so declared commands can still be found and behave sanely.
+const INHERITED_ENV : [&str; 2] = ["PATH", "HOME"];
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawCommand {
+    /// `"youtube-dl"` -- any arguments, anywhere.
+    Name(String),
+    Full(FullCommand),
+}
+
+// A misspelt restriction (`"forbids"`) must not silently allow everything.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FullCommand {
+    name   : String,
+    args   : Option<Vec<Vec<String>>>,
+    #[serde(default)]
+    forbid : Vec<String>,
+    env    : Option<BTreeMap<String, String>>,
+    cwd    : Option<Vec<String>>,
+}
+
+///
+/// ## CommandDeclaration
+///
+/// An external command from the manifest's `security.commands`:
+///
+/// ```json
+/// {
+///     "name"   : "git",
+///     "args"   : [["status"], ["log", "--oneline", "**"]],
+///     "forbid" : ["--force", "-f"],
+///     "env"    : { "GIT_TERMINAL_PROMPT": "0" },
+///     "cwd"    : ["~/Projects/**"]
+/// }
+/// ```
+///
+/// * `args` -- the allowed argument lists. Each argument is a glob (`*`), and a trailing `**` allows any remaining arguments.
+///   Without `args`, any arguments are allowed.
+/// * `forbid` -- flags which may never be passed -- nor as `--flag=value`, an abbreviation (`--forc`),
+///   or within combined short flags (`-fq` for `-f`).
+/// * `env` -- the command's environment. If set, it runs with only these variables (plus `PATH` and `HOME`).
+/// * `cwd` -- path patterns the command may run in.
+///
+/// A plain string (`"youtube-dl"`) declares a command with no restrictions.
+///
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "RawCommand")]
+pub struct CommandDeclaration {
+    pub name   : String,
+    pub args   : Option<Vec<Vec<String>>>,
+    pub forbid : Vec<String>,
+    pub env    : Option<BTreeMap<String, String>>,
+    pub cwd    : Option<Vec<String>>,
+}
+
+impl From<RawCommand> for CommandDeclaration {
+    fn from(raw: RawCommand) -> Self {
+        match raw {
+            RawCommand::Name(name) => CommandDeclaration::new(&name),
+            RawCommand::Full(FullCommand { name, args, forbid, env, cwd }) => CommandDeclaration { name, args, forbid, env, cwd },
+        }
+    }
+}
+
+fn arg_matches(pattern: &str, arg: &str) -> bool {
+    globset::Glob::new(pattern)
+        .map(|g| g.compile_matcher().is_match(arg))
+        .unwrap_or(false)
+}
+
+fn args_match(pattern: &[String], args: &[String]) -> bool {
+    match (pattern.split_first(), args.split_first()) {
+        (None, None)                     => true,
+        (Some((p, _)), _) if p == "**"   => true,
+        (Some((p, ps)), Some((a, rest))) => arg_matches(p, a) && args_match(ps, rest),
+        _                                => false,
+    }
+}
+
+impl CommandDeclaration {
+    pub fn new(name: &str) -> CommandDeclaration {
+        CommandDeclaration {
+            name   : name.to_string(),
+            args   : None,
+            forbid : vec![],
+            env    : None,
+            cwd    : None,
+        }
+    }
+
+    /// Whether an argument is (or contains) a forbidden flag.
+    fn is_forbidden(&self, arg: &str) -> bool {
+        let flag = arg.split("=").next().unwrap();
+
+        if self.forbid.iter().any(|f| f == arg || f == flag) {
+            return true;
+        }
+
+        // `--forc` -- long options can be abbreviated, as long as that's unambiguous.
+        if flag.starts_with("--") && flag.len() > 2 {
+            return self.forbid.iter().any(|f| f.starts_with("--") && f.starts_with(flag));
+        }
+
+        // `-vf` -- short flags can be combined. A value can't be told apart from flags
+        // (`-ofile`), so any forbidden letter counts.
+        if flag.starts_with("-") && flag.len() > 2 {
+            return arg[1..].chars().any(|c| self.forbid.iter().any(|f| *f == format!("-{}", c)));
+        }
+
+        false
+    }
+
+    /// Checks an invocation of this command, returning why it isn't allowed.
+    pub fn check(&self, args: &[String], cwd: &Path) -> Result<(), String> {
+        for arg in args {
+            if self.is_forbidden(arg) {
+                return Err(format!("`{}` is forbidden for `{}`.", arg, self.name));
+            }
+        }
+
+        if let Some(patterns) = &self.args {
+            if !patterns.iter().any(|p| args_match(p, args)) {
+                return Err(format!("`{} {}` does not match any declared arguments.", self.name, args.join(" ")));
+            }
+        }
+
+        if let Some(dirs) = &self.cwd {
+            let cwd = cwd.to_string_lossy();
+
+            if !dirs.iter().any(|d| resource_matches(d, &cwd)) {
+                return Err(format!("`{}` may not run in `{}`.", self.name, cwd));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the declared environment to a command about to be spawned.
+    pub fn apply(&self, cmd: &mut process::Command) -> () {
+        if let Some(env) = &self.env {
+            cmd.env_clear();
+
+            for k in INHERITED_ENV {
+                if let Ok(v) = std::env::var(k) {
+                    cmd.env(k, v);
+                }
+            }
+
+            cmd.envs(env);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::CommandDeclaration;
+
+    fn git() -> CommandDeclaration {
+        serde_json::from_str(r#"{
+            "name"   : "git",
+            "args"   : [["status"], ["push", "**"]],
+            "forbid" : ["--force", "-f"],
+            "cwd"    : ["/tmp/projects/**"]
+        }"#).unwrap()
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn declared_arguments() {
+        let cwd = Path::new("/tmp/projects/a");
+
+        assert!(git().check(&args(&["status"]), cwd).is_ok());
+        assert!(git().check(&args(&["push", "origin", "main"]), cwd).is_ok());
+        assert!(git().check(&args(&["reset", "--hard"]), cwd).is_err());
+        assert!(git().check(&args(&["status"]), Path::new("/tmp/other")).is_err());
+    }
+
+    #[test]
+    fn forbidden_flags() {
+        let cwd = Path::new("/tmp/projects/a");
+
+        for forbidden in [&["push", "--force"][..], &["push", "-f"], &["push", "--force=yes"], &["push", "--forc"], &["push", "--fo"], &["push", "-fq"], &["push", "-vf"]] {
+            assert!(git().check(&args(forbidden), cwd).is_err(), "{:?} should be forbidden", forbidden);
+        }
+
+        for allowed in [&["push", "--force-with-lease"][..], &["push", "--follow-tags"], &["push", "-v"], &["push", "--"]] {
+            assert!(git().check(&args(allowed), cwd).is_ok(), "{:?} should be allowed", allowed);
+        }
+    }
+
+    #[test]
+    fn unknown_fields() {
+        assert!(serde_json::from_str::<CommandDeclaration>(r#"{ "name": "git", "forbids": ["--force"] }"#).is_err());
+        assert!(serde_json::from_str::<CommandDeclaration>(r#""youtube-dl""#).is_ok());
+    }
+}
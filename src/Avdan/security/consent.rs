@@ -0,0 +1,123 @@
+use std::{io::{self, Write, BufRead}, sync::Arc, time::Duration};
+
+use colored::Colorize;
+use serde::{Serialize, Deserialize};
+
+/// How long "allow for now" grants last.
+const TEMPORARY : Duration = Duration::from_secs(60 * 60);
+
+pub enum Consent {
+    Deny,
+    /// Allowed, optionally only for a while.
+    Allow(Option<Duration>),
+}
+
+///
+/// ## ConsentProvider
+///
+/// Decides on the permissions extensions request at runtime (`Avdan.Security.request`).
+///
+/// Called from a task's thread, so it's free to block (e.g. on the user).
+///
+
+pub trait ConsentProvider : Send + Sync {
+    fn request(&self, extension: &str, permission: &str) -> Consent;
+}
+
+/// The provider for `--consent=<name>` (`terminal` by default).
+pub fn provider(name: &str) -> Option<Arc<dyn ConsentProvider>> {
+    match name {
+        "terminal" => Some(Arc::new(TerminalConsent {})),
+        "host"     => Some(Arc::new(HostConsent {})),
+        "deny"     => Some(Arc::new(DenyConsent {})),
+        _          => None,
+    }
+}
+
+///
+/// ## TerminalConsent
+///
+/// Asks whoever is at the terminal.
+///
+
+pub struct TerminalConsent {}
+
+impl ConsentProvider for TerminalConsent {
+    fn request(&self, extension: &str, permission: &str) -> Consent {
+        print!(
+            "\n{} {} is requesting '{}'.\n  [a]llow  allow for an [h]our  [D]eny: ",
+            "[security]".cyan(), extension.bold(), permission.yellow()
+        );
+        let _ = io::stdout().flush();
+
+        let mut answer = String::new();
+
+        if io::stdin().lock().read_line(&mut answer).is_err() {
+            return Consent::Deny;
+        }
+
+        match answer.trim().to_lowercase().as_str() {
+            "a" | "allow" => Consent::Allow(None),
+            "h" | "hour"  => Consent::Allow(Some(TEMPORARY)),
+            _             => Consent::Deny,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HostRequest<'a> {
+    r#type     : &'static str,
+    extension  : &'a str,
+    permission : &'a str,
+}
+
+#[derive(Deserialize)]
+struct HostReply {
+    allow      : bool,
+    /// Seconds.
+    expires_in : Option<u64>,
+}
+
+///
+/// ## HostConsent
+///
+/// Asks the host running the extension:
+/// writes `{"type":"consent_request","extension":...,"permission":...}` as a line on stdout,
+/// and reads `{"allow":true,"expires_in":3600}` back as a line from stdin.
+///
+
+pub struct HostConsent {}
+
+impl ConsentProvider for HostConsent {
+    fn request(&self, extension: &str, permission: &str) -> Consent {
+        let req = HostRequest { r#type: "consent_request", extension, permission };
+
+        println!("{}", serde_json::to_string(&req).unwrap());
+        let _ = io::stdout().flush();
+
+        let mut line = String::new();
+
+        if io::stdin().lock().read_line(&mut line).is_err() {
+            return Consent::Deny;
+        }
+
+        match serde_json::from_str::<HostReply>(&line) {
+            Ok(HostReply { allow: true, expires_in }) => Consent::Allow(expires_in.map(Duration::from_secs)),
+            _                                         => Consent::Deny,
+        }
+    }
+}
+
+///
+/// ## DenyConsent
+///
+/// Denies everything -- for unattended runs.
+///
+
+pub struct DenyConsent {}
+
+impl ConsentProvider for DenyConsent {
+    fn request(&self, _extension: &str, _permission: &str) -> Consent {
+        Consent::Deny
+    }
+}
@@ -0,0 +1,167 @@
+use std::{fs, io, path::PathBuf, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+use crate::Avdan::{Permission, runtime::{Sandbox, SandboxRequest, SandboxResponse}};
+
+use super::validate_permission;
+
+const GRANTS_DIR : &str = "avdan-js/grants";
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+///
+/// ## Grant
+///
+/// A permission the user granted at runtime (through `Avdan.Security.request`).
+///
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Grant {
+    pub permission : String,
+    /// UNIX timestamp (seconds).
+    pub granted    : u64,
+    /// UNIX timestamp (seconds), `None` for grants which never expire.
+    pub expires    : Option<u64>,
+}
+
+impl Grant {
+    pub fn new(permission: &str, expires_in: Option<Duration>) -> Grant {
+        let granted = now();
+
+        Grant {
+            permission : permission.to_string(),
+            granted,
+            expires    : expires_in.map(|d| granted + d.as_secs()),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires.map(|e| e <= now()).unwrap_or(false)
+    }
+
+    /// Checks a permission may be asked for at runtime: a wildcard (`*`, `avdan.*`, `avdan.net.*`...),
+    /// or a resource pattern matching anything, would grant a whole namespace with a single prompt.
+    pub fn validate_request(permission: &str) -> Result<(), String> {
+        validate_permission(permission)?;
+
+        let p = Permission::new(permission);
+
+        if p.name().contains("*") {
+            return Err(format!("`{}` -- only a single permission can be requested, not a wildcard!", permission));
+        }
+
+        match p.pattern() {
+            Some(pattern) if pattern.chars().all(|c| c == '*' || c == '/' || c == '?') =>
+                Err(format!("`{}` -- the resource pattern has to name what's requested!", permission)),
+            _ => Ok(()),
+        }
+    }
+}
+
+///
+/// ## Grants
+///
+/// Every runtime grant of an extension, persisted in `~/.local/share/avdan-js/grants/<hash>.json`.
+///
+/// Grants are keyed by the extension's [identity](crate::Avdan::loader::Extension::identity), not its name --
+/// anyone can call their extension after one the user trusts.
+///
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Grants {
+    /// The extension's identity.
+    #[serde(skip)]
+    extension : String,
+    grants    : Vec<Grant>,
+}
+
+impl Grants {
+    fn file(identity: &str) -> Option<PathBuf> {
+        let name = hex::encode(Sha256::digest(identity.as_bytes()));
+
+        dirs::data_dir().map(|d| d.join(GRANTS_DIR).join(format!("{}.json", name)))
+    }
+
+    /// Loads the (unexpired) grants of an extension, by its identity.
+    pub fn load(extension: &str) -> Grants {
+        let mut grants : Grants = match Sandbox::is_child() {
+            // A sandboxed extension can't read the grants, its parent does (and knows whose they are).
+            true  => match Sandbox::request(&SandboxRequest::Grants) {
+                Ok(SandboxResponse::Grants { grants }) => Grants { extension: String::new(), grants },
+                _                                      => Grants::default(),
+            },
+            false => Self::file(extension)
+                .and_then(|f| fs::read(f).ok())
+                .and_then(|b| serde_json::from_slice(&b).ok())
+                .unwrap_or_default(),
+        };
+
+        grants.extension = extension.to_string();
+        grants.grants.retain(|g| !g.is_expired());
+        grants
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        // A sandboxed extension can't write its grants, its parent does (and only takes revocations).
+        if Sandbox::is_child() {
+            return Sandbox::request(&SandboxRequest::SaveGrants { grants: self.grants.clone() })
+                .map(|_| ())
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e));
+        }
+
+        let file = Self::file(&self.extension)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No data directory!"))?;
+
+        fs::create_dir_all(file.parent().unwrap())?;
+        fs::write(file, serde_json::to_vec_pretty(self).unwrap())
+    }
+
+    pub fn extension(&self) -> &str {
+        &self.extension
+    }
+
+    pub fn list(&self) -> &Vec<Grant> {
+        &self.grants
+    }
+
+    pub fn has(&self, perm: &str) -> bool {
+        self.grants.iter()
+            .filter(|g| !g.is_expired())
+            .map(|g| Permission::new(&g.permission))
+            .any(|p| !p.is_scoped() && p.has(perm))
+    }
+
+    pub fn has_resource(&self, perm: &str, resource: &str) -> bool {
+        self.grants.iter()
+            .filter(|g| !g.is_expired())
+            .any(|g| Permission::new(&g.permission).has_resource(perm, resource))
+    }
+
+    /// Adds a grant, replacing any earlier grant of the same permission.
+    pub fn add(&mut self, grant: Grant) -> () {
+        self.grants.retain(|g| g.permission != grant.permission);
+        self.grants.push(grant);
+    }
+
+    /// Removes the grant of a permission, returning whether there was one.
+    pub fn revoke(&mut self, perm: &str) -> bool {
+        let before = self.grants.len();
+        self.grants.retain(|g| g.permission != perm);
+
+        before != self.grants.len()
+    }
+
+    /** STATIC FUNCTIONS */
+
+    pub fn into_scope<'a>(self, scope: &mut v8::HandleScope<'a>) -> () {
+        scope.set_slot(self);
+    }
+
+    pub fn from_scope<'a>(scope: &'a mut v8::HandleScope) -> Option<&'a mut Grants> {
+        scope.get_slot_mut::<Grants>()
+    }
+}
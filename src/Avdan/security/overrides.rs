@@ -0,0 +1,255 @@
+use std::{path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+
+use colored::Colorize;
+
+use crate::Avdan::Permission;
+
+use super::{AuditEntry, AuditKind, AuditLog, Constraints, Policy, PolicyFile, registry, validate_permission};
+
+///
+/// ## Override
+///
+/// A command line change to the extension's permissions, for one run:
+/// * `--allow-net`, `--allow-net.fetch` -- `avdan.net.*`, `avdan.net.fetch`.
+/// * `--allow-net=api.example.com,...` -- only on those resources (hosts, URLs or paths).
+/// * `--allow-all` -- every permission (`avdan.*`).
+/// * `--deny-shell` -- `avdan.shell.*`, whatever the manifest (or a grant) says.
+///
+/// Overrides only change permissions: `--allow-shell` (or `--allow-all`) doesn't declare any command,
+/// so `Avdan.Shell.exec` still only runs the ones in the manifest's `commands`.
+///
+
+#[derive(Clone)]
+pub enum Override {
+    Allow { flag: String, permissions: Vec<String> },
+    Deny  { flag: String, permission: String },
+}
+
+impl Override {
+    pub fn flag(&self) -> &str {
+        match self {
+            Override::Allow { flag, .. } => flag,
+            Override::Deny  { flag, .. } => flag,
+        }
+    }
+}
+
+///
+/// ## Overrides
+///
+/// Every [`Override`] given. Allowed permissions are added to the manifest's, denied ones
+/// become a policy of their own -- so the system/user policy still has the last word,
+/// and a denial wins over everything.
+///
+
+#[derive(Clone, Default)]
+pub struct Overrides {
+    list : Vec<Override>,
+}
+
+impl Overrides {
+    /// Picks the `--allow-*`/`--deny-*` flags out of the arguments.
+    pub fn parse(args: &[String]) -> Result<Overrides, String> {
+        let mut list = vec![];
+
+        for arg in args {
+            let (name, value) = match arg.split_once("=") {
+                Some((n, v)) => (n, Some(v)),
+                None         => (arg.as_str(), None),
+            };
+
+            if name == "--allow-all" {
+                list.push(Override::Allow { flag: arg.clone(), permissions: vec!["avdan.*".to_string()] });
+                continue;
+            }
+
+            if let Some(name) = name.strip_prefix("--allow-") {
+                let permission = Self::permission(name)?;
+
+                let permissions = match value {
+                    None         => vec![permission],
+                    Some(values) => {
+                        // `--allow-net=` would otherwise allow nothing, without saying so.
+                        if values.split(",").any(|v| v.trim().is_empty()) {
+                            return Err(format!("`{}` -- a resource can't be empty!", arg));
+                        }
+
+                        values.split(",")
+                            .map(|v| format!("{}:{}", permission, Self::pattern(&permission, v.trim())))
+                            .collect()
+                    },
+                };
+
+                list.push(Override::Allow { flag: arg.clone(), permissions });
+                continue;
+            }
+
+            if let Some(name) = name.strip_prefix("--deny-") {
+                if value.is_some() {
+                    return Err(format!("`{}` -- --deny-* flags deny a whole permission, they don't take resources!", arg));
+                }
+
+                list.push(Override::Deny { flag: arg.clone(), permission: Self::permission(name)? });
+            }
+        }
+
+        Ok(Overrides { list })
+    }
+
+    /// `net` -> `avdan.net.*`, `net.fetch` -> `avdan.net.fetch`.
+    fn permission(name: &str) -> Result<String, String> {
+        let full = format!("avdan.{}", name);
+
+        let permission = match registry().iter().any(|p| p.name == full) {
+            true  => full,
+            false => format!("{}.*", full),
+        };
+
+        validate_permission(&permission)?;
+        Ok(permission)
+    }
+
+    /// Hosts become URL patterns for `avdan.net`, anything else is taken as it is.
+    fn pattern(permission: &str, value: &str) -> String {
+        match permission.starts_with("avdan.net") && !value.contains("://") {
+            true  => format!("*://{}/*", value),
+            false => value.to_string(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn list(&self) -> &Vec<Override> {
+        &self.list
+    }
+
+    /// Adjusts the extension's constraints (before the policy is applied).
+    pub fn apply(&self, constraints: &mut Constraints, policy: &mut Policy) -> () {
+        for o in self.list.iter() {
+            match o {
+                Override::Allow { permissions, .. } => constraints.permissions.extend(permissions.iter().cloned()),
+
+                Override::Deny { flag, permission } => {
+                    let mut file = PolicyFile::default();
+                    file.rules.permissions.deny.push(permission.clone());
+
+                    policy.files.push((PathBuf::from(flag), file));
+                },
+            }
+        }
+    }
+
+    /// Prints a banner, so a run with overrides can't be mistaken for a normal one.
+    pub fn report(&self) -> () {
+        if self.is_empty() {
+            return;
+        }
+
+        println!("{}", "[overrides] This run does not follow the extension's manifest:".bright_yellow().bold());
+
+        for o in self.list.iter() {
+            match o {
+                Override::Allow { flag, permissions } => println!("  {} {} ({})", "allow".green(), permissions.join(", "), flag.dimmed()),
+                Override::Deny  { flag, permission }  => println!("  {} {} ({})", "deny ".bright_red(), permission, flag.dimmed()),
+            }
+        }
+
+        let shell = self.list.iter().any(|o| match o {
+            Override::Allow { permissions, .. } => permissions.iter().any(|p| Permission::new(p).has("avdan.shell.exec")),
+            Override::Deny  { .. }              => false,
+        });
+
+        if shell {
+            println!("  {}", "Commands still have to be declared in the manifest's `commands` to be run.".dimmed());
+        }
+    }
+
+    /// Records every override in the audit log.
+    pub fn audit(&self, extension: &str, identity: &str) -> () {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        let entries = self.list.iter().flat_map(|o| match o {
+            Override::Allow { permissions, .. } => permissions.iter().map(|p| (p.clone(), true)).collect::<Vec<_>>(),
+            Override::Deny  { permission, .. }  => vec![(permission.clone(), false)],
+        }.into_iter().map(move |(permission, allowed)| (o.flag().to_string(), permission, allowed)));
+
+        for (flag, permission, allowed) in entries {
+            let entry = AuditEntry {
+                timestamp,
+                extension  : extension.to_string(),
+                identity   : Some(identity.to_string()),
+                kind       : AuditKind::Override,
+                permission,
+                resource   : Some(flag),
+                allowed,
+                location   : None,
+            };
+
+            if let Err(err) = AuditLog::append(&entry) {
+                println!("Could not write to the audit log: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Override, Overrides};
+
+    fn parse(args: &[&str]) -> Result<Overrides, String> {
+        Overrides::parse(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>())
+    }
+
+    fn allowed(args: &[&str]) -> Vec<String> {
+        parse(args).unwrap().list().iter()
+            .flat_map(|o| match o {
+                Override::Allow { permissions, .. } => permissions.clone(),
+                Override::Deny  { .. }              => vec![],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn allows_permissions() {
+        assert_eq!(allowed(&["--allow-net"]), vec!["avdan.net.*"]);
+        assert_eq!(allowed(&["--allow-net.fetch"]), vec!["avdan.net.fetch"]);
+        assert_eq!(allowed(&["--allow-all"]), vec!["avdan.*"]);
+    }
+
+    #[test]
+    fn allows_resources() {
+        assert_eq!(
+            allowed(&["--allow-net=api.example.com,https://example.org/*"]),
+            vec!["avdan.net.*:*://api.example.com/*", "avdan.net.*:https://example.org/*"]
+        );
+        assert_eq!(allowed(&["--allow-file.read=~/Documents/**"]), vec!["avdan.file.read:~/Documents/**"]);
+    }
+
+    #[test]
+    fn denies_permissions() {
+        let overrides = parse(&["--deny-shell"]).unwrap();
+
+        match overrides.list().as_slice() {
+            [Override::Deny { flag, permission }] => {
+                assert_eq!(flag, "--deny-shell");
+                assert_eq!(permission, "avdan.shell.*");
+            },
+            _ => panic!("Expected a single denial!"),
+        }
+    }
+
+    #[test]
+    fn rejects_bad_flags() {
+        assert!(parse(&["--allow-net="]).is_err());
+        assert!(parse(&["--allow-net=api.example.com,,example.org"]).is_err());
+        assert!(parse(&["--deny-shell=ls"]).is_err());
+        assert!(parse(&["--allow-nett"]).is_err());
+    }
+
+    #[test]
+    fn ignores_other_arguments() {
+        assert!(parse(&["--watch", "--sandbox", "ext"]).unwrap().is_empty());
+    }
+}
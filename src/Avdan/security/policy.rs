@@ -0,0 +1,294 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}};
+
+use serde::{Serialize, Deserialize};
+
+use crate::Avdan::Permission;
+use super::{Constraints, Quota, registry, validate_permission};
+
+const SYSTEM_POLICY : &str = "/etc/avdan-js/policy.json";
+const USER_POLICY   : &str = "avdan-js/policy.json";
+
+///
+/// ## Lists
+///
+/// `{ "allow": [...], "deny": [...] }` -- without an `allow` list everything is allowed,
+/// and `deny` always wins.
+///
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Lists {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow : Option<Vec<String>>,
+    #[serde(default)]
+    pub deny  : Vec<String>,
+}
+
+impl Lists {
+    fn permits(&self, matches: impl Fn(&str) -> bool) -> bool {
+        !self.deny.iter().any(|d| matches(d))
+            && self.allow.as_ref().map(|a| a.iter().any(|e| matches(e))).unwrap_or(true)
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Rules {
+    #[serde(default)]
+    pub permissions : Lists,
+    #[serde(default)]
+    pub commands    : Lists,
+    /// `false` disallows code generation from strings, whatever the manifest says.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eval        : Option<bool>,
+    /// Quotas, enforced alongside the manifest's.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub quotas      : BTreeMap<String, Quota>,
+}
+
+impl Rules {
+    /// Permission entries are names, or wildcards (`avdan.shell.*`).
+    pub fn permits_permission(&self, permission: &str) -> bool {
+        self.permissions.permits(|e| Permission::new(e).has(permission))
+    }
+
+    /// Command entries are names, or `*`.
+    pub fn permits_command(&self, command: &str) -> bool {
+        self.commands.permits(|e| e == "*" || e == command)
+    }
+}
+
+///
+/// ## policy.json
+///
+/// Top-level rules, and per-extension rules which apply on top of them:
+///
+/// ```json
+/// {
+///     "permissions": { "deny": ["avdan.shell.*"] },
+///     "commands":    { "allow": ["git"] },
+///     "extensions":  { "git-search@acme": { "permissions": { "deny": ["avdan.net.*"] } } }
+/// }
+/// ```
+///
+/// Extensions are keyed by their [identity](crate::Avdan::loader::Extension::identity) --
+/// `name@publisher` if signed by a trusted publisher, `name@/path/to/it` otherwise -- as any extension
+/// can call itself `git-search`. Both rules have to permit something, so an extension's entry can only narrow
+/// the top-level rules (e.g. deny it more), never lift them.
+///
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PolicyFile {
+    #[serde(flatten)]
+    pub rules      : Rules,
+    #[serde(default)]
+    pub extensions : BTreeMap<String, Rules>,
+}
+
+impl PolicyFile {
+    /// The top-level rules, and the extension's own (if any).
+    pub fn rules_for(&self, identity: &str) -> Vec<&Rules> {
+        let mut rules = vec![&self.rules];
+        rules.extend(self.extensions.get(identity));
+
+        rules
+    }
+
+    /// Checks every quota key against the registry -- a typo would silently leave a quota out.
+    pub fn validate(&self) -> Result<(), String> {
+        let errors : Vec<String> = std::iter::once(&self.rules).chain(self.extensions.values())
+            .flat_map(|r| r.quotas.keys())
+            .filter_map(|key| validate_permission(key).err())
+            .collect();
+
+        match errors.is_empty() {
+            true  => Ok(()),
+            false => Err(errors.join("\n")),
+        }
+    }
+}
+
+///
+/// ## Policy
+///
+/// What the system (`/etc/avdan-js/policy.json`) and the user (`~/.config/avdan-js/policy.json`)
+/// allow extensions to do, regardless of their manifests.
+///
+/// Every file applies, so the user's policy can only narrow the system's.
+///
+
+#[derive(Clone, Default)]
+pub struct Policy {
+    pub files : Vec<(PathBuf, PolicyFile)>,
+}
+
+impl Policy {
+    fn paths() -> Vec<PathBuf> {
+        let mut paths = vec![PathBuf::from(SYSTEM_POLICY)];
+        paths.extend(dirs::config_dir().map(|d| d.join(USER_POLICY)));
+
+        paths
+    }
+
+    fn read(path: &Path) -> Result<PolicyFile, String> {
+        let contents = fs::read(path).map_err(|e| e.to_string())?;
+
+        let file : PolicyFile = serde_json::from_slice(&contents)
+            .map_err(|e| format!("Failed to parse policy `{}`: {}", path.to_str().unwrap(), e))?;
+
+        file.validate()
+            .map_err(|e| format!("Invalid policy `{}`:\n{}", path.to_str().unwrap(), e))?;
+
+        Ok(file)
+    }
+
+    /// Loads every policy file which exists. A policy which can't be read is an error, not an empty policy.
+    pub fn load() -> Result<Policy, String> {
+        let mut files = vec![];
+
+        for path in Self::paths().into_iter().filter(|p| p.exists()) {
+            let file = Self::read(&path)?;
+            files.push((path, file));
+        }
+
+        Ok(Policy { files })
+    }
+
+    /// The rules of every file, for an extension (by its identity).
+    pub fn rules_for(&self, identity: &str) -> Vec<Rules> {
+        self.files.iter().flat_map(|(_, f)| f.rules_for(identity)).cloned().collect()
+    }
+
+    fn denied_by(&self, identity: &str, permits: impl Fn(&Rules) -> bool) -> Option<&PathBuf> {
+        self.files.iter()
+            .find(|(_, f)| !f.rules_for(identity).into_iter().all(|r| permits(r)))
+            .map(|(p, _)| p)
+    }
+
+    /// Narrows a manifest permission to what the policy permits, returning what's left of it.
+    ///
+    /// Wildcards which are partly denied are expanded to the registered permissions they still cover,
+    /// e.g. `avdan.*` with `avdan.shell.*` denied becomes `avdan.net.fetch`, `avdan.wasm`...
+    fn narrow(&self, identity: &str, permission: &str, removed: &mut Vec<String>) -> Vec<String> {
+        let p = Permission::new(permission);
+        let pattern = permission.strip_prefix(p.name()).unwrap_or("");
+
+        let covered : Vec<&str> = registry().into_iter()
+            .map(|r| r.name)
+            .filter(|r| p.has(r))
+            .collect();
+
+        let mut kept = vec![];
+
+        for name in covered.iter() {
+            match self.denied_by(identity, |r| r.permits_permission(name)) {
+                Some(file) => removed.push(format!("'{}' (denied by `{}`)", name, file.to_str().unwrap())),
+                None       => kept.push(format!("{}{}", name, pattern)),
+            }
+        }
+
+        match kept.len() == covered.len() {
+            true  => vec![permission.to_string()],
+            false => kept,
+        }
+    }
+
+    /// Intersects an extension's constraints with the policy (for its identity),
+    /// returning the effective constraints and a note for everything removed.
+    pub fn apply(&self, identity: &str, constraints: Constraints) -> (Constraints, Vec<String>) {
+        let mut removed = vec![];
+
+        let permissions = constraints.permissions.iter()
+            .flat_map(|p| self.narrow(identity, p, &mut removed))
+            .collect();
+
+        let commands = constraints.commands.into_iter()
+            .filter(|c| match self.denied_by(identity, |r| r.permits_command(&c.name)) {
+                Some(file) => {
+                    removed.push(format!("`{}` (denied by `{}`)", c.name, file.to_str().unwrap()));
+                    false
+                },
+                None => true,
+            })
+            .collect();
+
+        let eval = match self.denied_by(identity, |r| r.eval != Some(false)) {
+            Some(file) if constraints.eval => {
+                removed.push(format!("code generation from strings (denied by `{}`)", file.to_str().unwrap()));
+                false
+            },
+            _ => constraints.eval,
+        };
+
+        // The policies' quotas are kept with their rules, and enforced alongside the manifest's.
+        let constraints = Constraints {
+            permissions,
+            commands,
+            eval,
+            quotas : constraints.quotas,
+            policy : self.rules_for(identity),
+        };
+
+        (constraints, removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{Constraints, Policy};
+
+    fn policy(json: &str) -> Policy {
+        Policy { files: vec![(PathBuf::from("/etc/avdan-js/policy.json"), serde_json::from_str(json).unwrap())] }
+    }
+
+    fn manifest() -> Constraints {
+        Constraints::new(vec!["avdan.net.fetch", "avdan.shell.exec"], vec!["git", "rm"])
+    }
+
+    fn commands(c: &Constraints) -> Vec<&str> {
+        c.commands().iter().map(|c| c.name.as_str()).collect()
+    }
+
+    #[test]
+    fn top_level_rules() {
+        let policy = policy(r#"{ "permissions": { "deny": ["avdan.shell.*"] }, "commands": { "allow": ["git"] } }"#);
+        let (constraints, removed) = policy.apply("search@acme", manifest());
+
+        assert_eq!(constraints.permissions(), &vec!["avdan.net.fetch".to_string()]);
+        assert_eq!(commands(&constraints), vec!["git"]);
+        assert_eq!(removed.len(), 2);
+    }
+
+    #[test]
+    fn partly_denied_wildcard() {
+        let policy = policy(r#"{ "permissions": { "deny": ["avdan.shell.*"] } }"#);
+        let (constraints, _) = policy.apply("search@acme", Constraints::new(vec!["avdan.*"], vec![]));
+
+        assert!(constraints.permissions().contains(&"avdan.net.fetch".to_string()));
+        assert!(!constraints.permissions().iter().any(|p| p.starts_with("avdan.shell") || p == "avdan.*"));
+    }
+
+    #[test]
+    fn extension_rules_add_to_top_level() {
+        let policy = policy(r#"{
+            "permissions": { "deny": ["avdan.shell.*"] },
+            "extensions":  { "search@acme": { "permissions": { "deny": ["avdan.net.*"], "allow": ["avdan.shell.exec"] } } }
+        }"#);
+
+        let (constraints, _) = policy.apply("search@acme", manifest());
+        assert!(constraints.permissions().is_empty());
+
+        // Only the top-level rules for anyone else.
+        let (constraints, _) = policy.apply("search@/tmp/search", manifest());
+        assert_eq!(constraints.permissions(), &vec!["avdan.net.fetch".to_string()]);
+    }
+
+    #[test]
+    fn extension_rules_by_identity_not_name() {
+        let policy = policy(r#"{ "extensions": { "search@acme": { "commands": { "deny": ["rm"] } } } }"#);
+
+        assert_eq!(commands(&policy.apply("search@acme", manifest()).0), vec!["git"]);
+        assert_eq!(commands(&policy.apply("search@/tmp/search", manifest()).0), vec!["git", "rm"]);
+        assert_eq!(commands(&policy.apply("search", manifest()).0), vec!["git", "rm"]);
+    }
+}
@@ -0,0 +1,352 @@
+use std::{collections::{HashMap, VecDeque}, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use serde::{Serialize, Deserialize, Deserializer, de::Error};
+use v8::{HandleScope, Local, Value};
+
+use crate::core::def_safe_property;
+
+pub const QUOTA_EXCEEDED : &str = "QuotaExceeded";
+
+fn minute() -> u64 {
+    60
+}
+
+// A period of 0 would empty the window on every call -- no limit at all.
+fn period<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    match u64::deserialize(deserializer)? {
+        0   => Err(D::Error::custom("a quota's `per` has to be at least 1 (second)")),
+        per => Ok(per),
+    }
+}
+
+///
+/// ## Quota
+///
+/// A limit on how much a permission can be used, per period -- in the manifest's (or a policy's) `quotas`:
+///
+/// ```json
+/// "quotas": {
+///     "avdan.net.fetch": { "calls": 60, "bytes": 10485760, "per": 60 }
+/// }
+/// ```
+///
+/// Quotas from the manifest and policies are all enforced, each in its own window --
+/// 30 calls an hour *and* 100 a minute can't be folded into a single limit.
+///
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Quota {
+    /// Calls per period.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calls : Option<u64>,
+    /// Bytes (e.g. of response bodies) per period.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes : Option<u64>,
+    /// The period, in seconds (a minute by default).
+    #[serde(default = "minute", deserialize_with = "period")]
+    pub per   : u64,
+}
+
+impl Quota {
+    fn period(&self) -> Duration {
+        Duration::from_secs(self.per)
+    }
+}
+
+// Usage is counted per key and period, so two quotas of a key with different periods don't share a window.
+fn window_key(key: &str, quota: &Quota) -> String {
+    format!("{}/{}s", key, quota.per)
+}
+
+#[derive(Default)]
+struct Window {
+    calls    : VecDeque<Instant>,
+    bytes    : VecDeque<(Instant, u64)>,
+    /// Bytes held by transfers still running (see [`Reservation`]).
+    reserved : u64,
+}
+
+impl Window {
+    fn prune(&mut self, per: Duration) -> () {
+        let now = Instant::now();
+
+        while self.calls.front().map(|t| now.duration_since(*t) >= per).unwrap_or(false) {
+            self.calls.pop_front();
+        }
+
+        while self.bytes.front().map(|(t, _)| now.duration_since(*t) >= per).unwrap_or(false) {
+            self.bytes.pop_front();
+        }
+    }
+
+    fn bytes(&self) -> u64 {
+        self.bytes.iter().map(|(_, b)| b).sum::<u64>() + self.reserved
+    }
+}
+
+type Windows = Arc<Mutex<HashMap<String, Window>>>;
+
+///
+/// ## Reservation
+///
+/// What's left of the byte quotas of a permission, held for one transfer from the moment it's made
+/// -- so transfers running side by side can't each spend the whole budget.
+///
+/// When dropped (on the transfer's thread, whether it succeeded or not), the bytes it used count
+/// against the quotas and the rest is released.
+///
+
+pub struct Reservation {
+    windows : Windows,
+    keys    : Vec<String>,
+    budget  : u64,
+    used    : u64,
+}
+
+impl Reservation {
+    /// How many bytes the transfer may use.
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    /// Sets how many bytes the transfer used.
+    pub fn used(&mut self, bytes: u64) -> () {
+        self.used = bytes;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut windows = self.windows.lock().unwrap();
+
+        for key in self.keys.iter() {
+            if let Some(window) = windows.get_mut(key) {
+                window.reserved = window.reserved.saturating_sub(self.budget);
+                window.bytes.push_back((Instant::now(), self.used));
+            }
+        }
+    }
+}
+
+///
+/// ## Usage
+///
+/// How much of each quota the extension has used, in a sliding window (kept in the isolate).
+///
+
+#[derive(Default)]
+pub struct Usage {
+    windows : Windows,
+}
+
+impl Usage {
+    pub fn from_scope<'a>(scope: &'a mut HandleScope) -> &'a mut Usage {
+        if scope.get_slot::<Usage>().is_none() {
+            scope.set_slot(Usage::default());
+        }
+
+        scope.get_slot_mut::<Usage>().unwrap()
+    }
+
+    /// Counts a call against every quota given, unless one of them is used up.
+    pub fn call<'q>(&mut self, quotas: impl Iterator<Item = (&'q String, &'q Quota)>) -> Result<(), String> {
+        let mut windows = self.windows.lock().unwrap();
+        let mut keys = vec![];
+
+        for (key, quota) in quotas {
+            let id = window_key(key, quota);
+            let window = windows.entry(id.clone()).or_default();
+            window.prune(quota.period());
+
+            if let Some(calls) = quota.calls {
+                if window.calls.len() as u64 >= calls {
+                    return Err(format!("'{}' can only be used {} times every {}s.", key, calls, quota.per));
+                }
+            }
+
+            if let Some(bytes) = quota.bytes {
+                if window.bytes() >= bytes {
+                    return Err(format!("'{}' can only transfer {} bytes every {}s.", key, bytes, quota.per));
+                }
+            }
+
+            // Quotas of the same key and period (e.g. the manifest's and a policy's) share a window.
+            if !keys.contains(&id) {
+                keys.push(id);
+            }
+        }
+
+        let now = Instant::now();
+
+        for key in keys {
+            windows.get_mut(&key).unwrap().calls.push_back(now);
+        }
+
+        Ok(())
+    }
+
+    /// Holds what's left of every byte quota given (`None` if none of them limits bytes).
+    pub fn reserve_bytes<'q>(&mut self, quotas: impl Iterator<Item = (&'q String, &'q Quota)>) -> Option<Reservation> {
+        let mut windows = self.windows.lock().unwrap();
+
+        let mut keys : Vec<String> = vec![];
+        let mut budget : Option<u64> = None;
+
+        for (key, quota) in quotas {
+            let limit = match quota.bytes {
+                Some(l) => l,
+                None    => continue,
+            };
+
+            let id = window_key(key, quota);
+            let window = windows.entry(id.clone()).or_default();
+            window.prune(quota.period());
+
+            let remaining = limit.saturating_sub(window.bytes());
+            budget = Some(budget.map_or(remaining, |b| b.min(remaining)));
+
+            if !keys.contains(&id) {
+                keys.push(id);
+            }
+        }
+
+        let budget = budget?;
+
+        for key in keys.iter() {
+            windows.get_mut(key).unwrap().reserved += budget;
+        }
+
+        Some(Reservation {
+            windows : self.windows.clone(),
+            keys,
+            budget,
+            used    : 0,
+        })
+    }
+}
+
+/// A `QuotaExceeded` error -- an `Error` whose `name` is `QuotaExceeded`.
+pub fn quota_exception<'a>(scope: &mut HandleScope<'a>, message: &str) -> Local<'a, Value> {
+    let msg = v8::String::new(scope, message).unwrap();
+    let err = v8::Exception::error(scope, msg);
+
+    if let Ok(obj) = err.try_into() {
+        let name = v8::String::new(scope, QUOTA_EXCEEDED).unwrap();
+        def_safe_property(scope, obj, "name", name.into());
+    }
+
+    err
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Avdan::security::{Constraints, PolicyFile};
+
+    fn quota(calls: Option<u64>, bytes: Option<u64>, per: u64) -> Quota {
+        Quota { calls, bytes, per }
+    }
+
+    #[test]
+    fn quotas_with_different_periods_are_enforced_separately() {
+        let key = "avdan.net.fetch".to_string();
+        let hourly = quota(Some(30), None, 3600);
+        let minutely = quota(Some(100), None, 60);
+        let quotas = [(&key, &hourly), (&key, &minutely)];
+
+        let mut usage = Usage::default();
+
+        for _ in 0..30 {
+            assert!(usage.call(quotas.iter().copied()).is_ok());
+        }
+
+        assert!(usage.call(quotas.iter().copied()).unwrap_err().contains("30 times every 3600s"));
+    }
+
+    #[test]
+    fn tighter_period_limits_first() {
+        let key = "avdan.net.*".to_string();
+        let hourly = quota(Some(100), None, 3600);
+        let minutely = quota(Some(2), None, 60);
+        let quotas = [(&key, &hourly), (&key, &minutely)];
+
+        let mut usage = Usage::default();
+
+        assert!(usage.call(quotas.iter().copied()).is_ok());
+        assert!(usage.call(quotas.iter().copied()).is_ok());
+        assert!(usage.call(quotas.iter().copied()).unwrap_err().contains("2 times every 60s"));
+    }
+
+    #[test]
+    fn quotas_sharing_a_window_count_a_call_once() {
+        let key = "avdan.net.fetch".to_string();
+        let manifest = quota(Some(2), None, 60);
+        let policy = quota(Some(5), None, 60);
+        let quotas = [(&key, &manifest), (&key, &policy)];
+
+        let mut usage = Usage::default();
+
+        assert!(usage.call(quotas.iter().copied()).is_ok());
+        assert!(usage.call(quotas.iter().copied()).is_ok());
+        assert!(usage.call(quotas.iter().copied()).is_err());
+    }
+
+    #[test]
+    fn reservations_hold_the_budget() {
+        let key = "avdan.net.fetch".to_string();
+        let limit = quota(None, Some(100), 60);
+        let quotas = [(&key, &limit)];
+
+        let mut usage = Usage::default();
+
+        let mut first = usage.reserve_bytes(quotas.iter().copied()).unwrap();
+        assert_eq!(first.budget(), 100);
+
+        // A transfer started meanwhile gets nothing.
+        assert_eq!(usage.reserve_bytes(quotas.iter().copied()).unwrap().budget(), 0);
+
+        first.used(40);
+        drop(first);
+
+        assert_eq!(usage.reserve_bytes(quotas.iter().copied()).unwrap().budget(), 60);
+    }
+
+    #[test]
+    fn reservations_take_the_smallest_budget() {
+        let key = "avdan.net.*".to_string();
+        let small = quota(None, Some(10), 60);
+        let large = quota(None, Some(1000), 3600);
+        let calls = quota(Some(5), None, 60);
+        let quotas = [(&key, &small), (&key, &large), (&key, &calls)];
+
+        let mut usage = Usage::default();
+
+        assert_eq!(usage.reserve_bytes(quotas.iter().copied()).unwrap().budget(), 10);
+        assert!(usage.reserve_bytes([(&key, &calls)].iter().copied()).is_none());
+    }
+
+    #[test]
+    fn zero_periods_are_rejected() {
+        assert!(serde_json::from_str::<Quota>(r#"{ "calls": 10, "per": 0 }"#).is_err());
+        assert_eq!(serde_json::from_str::<Quota>(r#"{ "calls": 10 }"#).unwrap().per, 60);
+        assert_eq!(serde_json::from_str::<Quota>(r#"{ "calls": 10, "per": 1 }"#).unwrap().per, 1);
+    }
+
+    #[test]
+    fn quota_keys_are_validated() {
+        let manifest = |key: &str| serde_json::from_str::<Constraints>(&format!(
+            r#"{{ "permissions": [], "commands": [], "quotas": {{ "{}": {{ "calls": 1 }} }} }}"#, key
+        )).unwrap();
+
+        assert!(manifest("avdan.net.fetch").validate().is_ok());
+        assert!(manifest("avdan.net.*").validate().is_ok());
+        assert!(manifest("avdan.net.fecth").validate().unwrap_err().contains("avdan.net.fecth"));
+
+        let policy = |key: &str| serde_json::from_str::<PolicyFile>(&format!(
+            r#"{{ "extensions": {{ "search@acme": {{ "quotas": {{ "{}": {{ "calls": 1 }} }} }} }} }}"#, key
+        )).unwrap();
+
+        assert!(policy("avdan.net.fetch").validate().is_ok());
+        assert!(policy("avdan.net.fecth").validate().is_err());
+    }
+}
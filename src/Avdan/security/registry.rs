@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+
+use crate::Avdan::Permission;
+
+///
+/// ## PermissionInfo
+///
+/// A permission guarded somewhere in the API. Every `#[permission(...)]` registers one,
+/// so the registry always matches what the API actually checks.
+///
+
+#[derive(Clone, Copy)]
+pub struct PermissionInfo {
+    pub name        : &'static str,
+    pub description : &'static str,
+}
+
+inventory::collect!(PermissionInfo);
+
+/// Every registered permission, sorted by name.
+/// (A permission guarding several functions keeps the first description given.)
+pub fn registry() -> Vec<PermissionInfo> {
+    let mut permissions : BTreeMap<&str, PermissionInfo> = BTreeMap::new();
+
+    for info in inventory::iter::<PermissionInfo> {
+        let entry = permissions.entry(info.name).or_insert(*info);
+
+        if entry.description.is_empty() {
+            entry.description = info.description;
+        }
+    }
+
+    permissions.into_values().collect()
+}
+
+fn distance(a: &str, b: &str) -> usize {
+    let b : Vec<char> = b.chars().collect();
+    let mut row : Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = (prev + (ca != *cb) as usize).min(row[j] + 1).min(cur + 1);
+            prev = cur;
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Checks a manifest permission (`avdan.net.fetch`, `avdan.net.*`, `avdan.file.read:~/**`...)
+/// against the registry -- a wildcard must cover at least one registered permission.
+pub fn validate_permission(permission: &str) -> Result<(), String> {
+    let name = Permission::new(permission).name().to_string();
+    let registry = registry();
+
+    let known = match name.strip_suffix("*") {
+        Some(prefix) => registry.iter().any(|p| p.name.starts_with(prefix)),
+        // A namespace (`avdan.net`) covers everything within it.
+        None         => registry.iter().any(|p| p.name == name || p.name.starts_with(&format!("{}.", name))),
+    };
+
+    if known {
+        return Ok(());
+    }
+
+    let suggestion = registry.iter()
+        .map(|p| (distance(&name, p.name), p.name))
+        .filter(|(d, _)| *d <= 3)
+        .min()
+        .map(|(_, p)| format!(" (did you mean '{}'?)", p))
+        .unwrap_or_default();
+
+    Err(format!("Unknown permission '{}'{}.", permission, suggestion))
+}
+
+/// Checks that a declared command is a program name (looked up on `PATH`) or an absolute path.
+/// Whether it's installed is only known when it's run -- a manifest shouldn't depend on the machine loading it.
+pub fn validate_command(command: &str) -> Result<(), String> {
+    let valid = !command.is_empty()
+        && !command.chars().any(|c| c.is_whitespace() || c.is_control())
+        && (!command.contains("/") || (command.starts_with("/") && !command.ends_with("/")));
+
+    match valid {
+        true  => Ok(()),
+        false => Err(format!("Invalid command `{}` -- expected a program name or an absolute path.", command)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use crate::Avdan::security::Constraints;
+
+    use super::{validate_command, validate_permission};
+
+    #[test]
+    fn registered_permissions() {
+        assert!(validate_permission("avdan.net.fetch").is_ok());
+        assert!(validate_permission("avdan.net.*").is_ok());
+        assert!(validate_permission("avdan.net").is_ok());
+        assert!(validate_permission("avdan.net.fetch:https://api.github.com/*").is_ok());
+        assert!(validate_permission("avdan.file.read:~/Documents/**").is_ok());
+        assert!(validate_permission("avdan.file.*").is_ok());
+    }
+
+    #[test]
+    fn unknown_permissions() {
+        assert!(validate_permission("avdan.nett.fetch").unwrap_err().contains("did you mean 'avdan.net.fetch'"));
+        assert!(validate_permission("avdan.teleport.*").is_err());
+        assert!(validate_permission("avdan.net.fetchall").is_err());
+    }
+
+    #[test]
+    fn commands() {
+        assert!(validate_command("youtube-dl").is_ok());
+        assert!(validate_command("/usr/bin/git").is_ok());
+
+        assert!(validate_command("").is_err());
+        assert!(validate_command("rm -rf").is_err());
+        assert!(validate_command("./tool").is_err());
+        assert!(validate_command("/usr/bin/").is_err());
+    }
+
+    #[test]
+    fn fixture_manifests() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("test");
+        let mut checked = 0;
+
+        for entry in fs::read_dir(fixtures).unwrap() {
+            let manifest = entry.unwrap().path().join("manifest.avdan.json");
+
+            if !manifest.exists() {
+                continue;
+            }
+
+            let json : serde_json::Value = serde_json::from_slice(&fs::read(&manifest).unwrap()).unwrap();
+            let constraints : Constraints = serde_json::from_value(json["security"].clone()).unwrap();
+
+            if let Err(err) = constraints.validate() {
+                panic!("`{}` is invalid:\n{}", manifest.to_string_lossy(), err);
+            }
+
+            checked += 1;
+        }
+
+        assert!(checked > 0);
+    }
+}
@@ -0,0 +1,236 @@
+use std::path::{Path, PathBuf, Component};
+
+use globset::GlobBuilder;
+
+/// `~/...` -> `$HOME/...`
+pub fn expand_home(pattern: &str) -> String {
+    match (pattern.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest).to_string_lossy().to_string(),
+        _                        => pattern.to_string(),
+    }
+}
+
+/// Resolves symlinks (for paths which exist), `.` and `..`,
+/// so `~/Documents/../.ssh/id_rsa` can't sneak past `~/Documents/**`.
+fn normalize_path(path: &str) -> String {
+    let path = Path::new(path).canonicalize().unwrap_or_else(|_| PathBuf::from(path));
+    let mut out = PathBuf::new();
+
+    for c in path.components() {
+        match c {
+            Component::ParentDir => { out.pop(); },
+            Component::CurDir    => {},
+            c                    => out.push(c),
+        }
+    }
+
+    out.to_string_lossy().to_string()
+}
+
+///
+/// ## UrlPattern
+///
+/// `scheme://host[:port]/path`, matched part by part against a parsed URL:
+/// * scheme -- `*` for any.
+/// * host -- `*` for any, otherwise `*` matches within a single DNS label (`https://*.example.com/*`).
+/// * port -- the scheme's default if left out, `*` for any.
+/// * path -- `*` matches anything, including `/`, but never the query or the fragment.
+///
+
+struct UrlPattern {
+    scheme : String,
+    host   : String,
+    port   : Option<String>,
+    path   : String,
+}
+
+impl UrlPattern {
+    fn parse(pattern: &str) -> Option<UrlPattern> {
+        let (scheme, rest) = pattern.split_once("://")?;
+
+        let (authority, path) = match rest.find("/") {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None    => (rest, "/"),
+        };
+
+        // `[::1]:8080` -- the port comes after the IPv6 address.
+        let port_at = match authority.starts_with("[") {
+            true  => authority.find("]").and_then(|i| authority[i..].find(":").map(|j| i + j)),
+            false => authority.rfind(":"),
+        };
+
+        let (host, port) = match port_at {
+            Some(i) => (&authority[..i], Some(authority[i + 1..].to_string())),
+            None    => (authority, None),
+        };
+
+        if scheme.is_empty() || host.is_empty() || authority.contains("@") {
+            return None;
+        }
+
+        Some(UrlPattern {
+            scheme : scheme.to_ascii_lowercase(),
+            host   : host.to_ascii_lowercase(),
+            port,
+            path   : path.to_string(),
+        })
+    }
+
+    fn matches_host(&self, host: &str) -> bool {
+        if self.host == "*" {
+            return true;
+        }
+
+        let labels : Vec<&str> = self.host.split(".").collect();
+        let actual : Vec<&str> = host.split(".").collect();
+
+        labels.len() == actual.len()
+            && labels.iter().zip(actual.iter()).all(|(pattern, label)| label_matches(pattern, label))
+    }
+
+    fn matches_port(&self, url: &reqwest::Url) -> bool {
+        match &self.port {
+            // The URL's port is only `None` if it's the scheme's default one.
+            None               => url.port().is_none(),
+            Some(p) if p == "*" => true,
+            Some(p)            => p.parse::<u16>().ok() == url.port_or_known_default(),
+        }
+    }
+
+    fn matches(&self, url: &reqwest::Url) -> bool {
+        let host = match url.host_str() {
+            Some(h) => h,
+            None    => return false,
+        };
+
+        let path = GlobBuilder::new(&self.path)
+            .literal_separator(false)
+            .build();
+
+        (self.scheme == "*" || self.scheme == url.scheme())
+            && self.matches_host(host)
+            && self.matches_port(url)
+            && match path {
+                Ok(glob) => glob.compile_matcher().is_match(url.path()),
+                Err(_)   => false,
+            }
+    }
+}
+
+/// `*` matches any run of characters, within a single DNS label.
+fn label_matches(pattern: &str, label: &str) -> bool {
+    let parts : Vec<&str> = pattern.split("*").collect();
+
+    let (first, last) = match parts.as_slice() {
+        [only]              => return *only == label,
+        [first, .., last]   => (*first, *last),
+        []                  => return false,
+    };
+
+    if label.is_empty() || label.len() < first.len() + last.len() || !label.starts_with(first) || !label.ends_with(last) {
+        return false;
+    }
+
+    let mut rest = &label[first.len()..label.len() - last.len()];
+
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None    => return false,
+        }
+    }
+
+    true
+}
+
+/// Whether a concrete resource falls within a permission's pattern.
+///
+/// * URLs (`https://api.github.com/*`) -- see [`UrlPattern`]; the query and fragment aren't matched.
+/// * Paths (`~/Documents/**`) -- `*` stays within a directory, `**` crosses them.
+pub fn resource_matches(pattern: &str, resource: &str) -> bool {
+    if pattern.contains("://") {
+        return match (UrlPattern::parse(pattern), reqwest::Url::parse(resource)) {
+            (Some(pattern), Ok(url)) => pattern.matches(&url),
+            _                        => false,
+        };
+    }
+
+    let glob = GlobBuilder::new(&expand_home(pattern))
+        .literal_separator(true)
+        .build();
+
+    match glob {
+        Ok(glob) => glob.compile_matcher().is_match(&normalize_path(&expand_home(resource))),
+        Err(_)   => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resource_matches;
+
+    #[test]
+    fn url_path_stays_on_its_host() {
+        let pattern = "https://api.github.com/*";
+
+        assert!(resource_matches(pattern, "https://api.github.com/repos/avdanos/search"));
+        assert!(resource_matches(pattern, "https://api.github.com/search?q=avdan#top"));
+
+        assert!(!resource_matches(pattern, "https://api.github.com.evil.com/"));
+        assert!(!resource_matches(pattern, "https://evil.com/?https://api.github.com/"));
+        assert!(!resource_matches(pattern, "https://evil.com/#https://api.github.com/"));
+        assert!(!resource_matches(pattern, "https://api.github.com@evil.com/"));
+        assert!(!resource_matches(pattern, "http://api.github.com/"));
+    }
+
+    #[test]
+    fn url_path_is_matched_without_the_query() {
+        let pattern = "https://api.github.com/repos/*";
+
+        assert!(resource_matches(pattern, "https://api.github.com/repos/a/b"));
+        assert!(!resource_matches(pattern, "https://api.github.com/users?next=/repos/a"));
+        assert!(!resource_matches(pattern, "https://api.github.com/users#/repos/a"));
+    }
+
+    #[test]
+    fn host_wildcards_stay_within_a_label() {
+        let pattern = "https://*.example.com/*";
+
+        assert!(resource_matches(pattern, "https://api.example.com/x"));
+
+        assert!(!resource_matches(pattern, "https://example.com/"));
+        assert!(!resource_matches(pattern, "https://a.b.example.com/"));
+        assert!(!resource_matches(pattern, "https://evil.com/.example.com/"));
+        assert!(!resource_matches(pattern, "https://evil.com?.example.com/"));
+    }
+
+    #[test]
+    fn any_scheme() {
+        let pattern = "*://example.com/*";
+
+        assert!(resource_matches(pattern, "http://example.com/"));
+        assert!(resource_matches(pattern, "https://example.com/a/b"));
+
+        assert!(!resource_matches(pattern, "https://example.com.evil.com/"));
+        assert!(!resource_matches(pattern, "https://evil.com/example.com/"));
+    }
+
+    #[test]
+    fn ports() {
+        assert!(resource_matches("https://example.com/*", "https://example.com:443/"));
+        assert!(!resource_matches("https://example.com/*", "https://example.com:8443/"));
+
+        assert!(resource_matches("https://example.com:8443/*", "https://example.com:8443/a"));
+        assert!(!resource_matches("https://example.com:8443/*", "https://example.com/a"));
+
+        assert!(resource_matches("http://localhost:*/*", "http://localhost:3000/"));
+    }
+
+    #[test]
+    fn paths() {
+        assert!(resource_matches("/tmp/avdan/*", "/tmp/avdan/a"));
+        assert!(!resource_matches("/tmp/avdan/*", "/tmp/avdan/a/b"));
+        assert!(resource_matches("/tmp/avdan/**", "/tmp/avdan/a/b"));
+        assert!(!resource_matches("/tmp/avdan/**", "/tmp/avdan/../etc/passwd"));
+    }
+}
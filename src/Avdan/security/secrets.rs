@@ -0,0 +1,272 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, DirBuilder, File, OpenOptions},
+    io::{self, Write},
+    os::unix::{fs::{DirBuilderExt, OpenOptionsExt}, io::AsRawFd},
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+};
+
+use chacha20poly1305::{aead::{Aead, KeyInit, Payload}, Key, XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use secret_service::{EncryptionType, blocking::SecretService};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use v8::HandleScope;
+
+use crate::Avdan::runtime::{Sandbox, SandboxRequest, SandboxResponse};
+
+// Not under `avdan-js/`, which a sandboxed extension can read (see [`Sandbox`]).
+const SECRETS_DIR : &str = "avdan-js-secrets";
+const KEY_FILE    : &str = "secrets.key";
+const LOCK_FILE   : &str = ".lock";
+const KEY_LABEL   : &str = "avdan-js extension secrets";
+const NONCE_LEN   : usize = 24;
+
+// How the key is found in the Secret Service.
+const KEY_ATTRIBUTES : [(&str, &str); 2] = [("application", "avdan-js"), ("purpose", "extension-secrets")];
+
+// Serializes changes within the process; the lock file, between processes.
+static LOCK : Mutex<()> = Mutex::new(());
+
+fn secrets_dir() -> Result<PathBuf, String> {
+    dirs::data_dir().map(|d| d.join(SECRETS_DIR)).ok_or("No data directory!".to_string())
+}
+
+/// Creates the secrets directory, readable by the user alone.
+fn create_secrets_dir() -> Result<PathBuf, String> {
+    let dir = secrets_dir()?;
+
+    DirBuilder::new().recursive(true).mode(0o700).create(&dir)
+        .map_err(|e| format!("Could not create `{}`: {}", dir.to_string_lossy(), e))?;
+
+    Ok(dir)
+}
+
+/// Held while the secrets (or their key) are read and written.
+struct SecretsLock {
+    _guard : MutexGuard<'static, ()>,
+    _file  : File,
+}
+
+impl SecretsLock {
+    fn acquire() -> Result<SecretsLock, String> {
+        // A panic while holding it doesn't leave the files in a bad state (they're replaced whole).
+        let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let path = create_secrets_dir()?.join(LOCK_FILE);
+        let file = OpenOptions::new().write(true).create(true).mode(0o600).open(&path)
+            .map_err(|e| format!("Could not open `{}`: {}", path.to_string_lossy(), e))?;
+
+        // Released when the file is closed.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(format!("Could not lock the secrets: {}", io::Error::last_os_error()));
+        }
+
+        Ok(SecretsLock { _guard: guard, _file: file })
+    }
+}
+
+/// What an extension does with its secrets.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum SecretAction {
+    Get    { key: String },
+    Set    { key: String, value: String },
+    Delete { key: String },
+}
+
+///
+/// ## SecretKey
+///
+/// The key every extension's secrets are encrypted with -- from the Secret Service (GNOME Keyring, KWallet...)
+/// when there is one, or else from `~/.local/share/avdan-js-secrets/secrets.key`. Either is generated on first use.
+///
+/// Once there's a key file, it's always used (so the secrets it encrypted stay readable).
+///
+
+pub struct SecretKey([u8; 32]);
+
+impl SecretKey {
+    pub fn load() -> Result<SecretKey, String> {
+        let file = secrets_dir()?.join(KEY_FILE);
+
+        if file.exists() {
+            return Self::from_file(&file);
+        }
+
+        Self::from_service().or_else(|_| Self::from_file(&file))
+    }
+
+    fn generate() -> SecretKey {
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+
+        SecretKey(key)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<SecretKey, String> {
+        bytes.try_into()
+            .map(SecretKey)
+            .map_err(|_| "The secrets key is corrupt!".to_string())
+    }
+
+    fn from_service() -> Result<SecretKey, String> {
+        let service    = SecretService::connect(EncryptionType::Dh).map_err(|e| e.to_string())?;
+        let collection = service.get_default_collection().map_err(|e| e.to_string())?;
+        collection.unlock().map_err(|e| e.to_string())?;
+
+        let attributes : HashMap<&str, &str> = KEY_ATTRIBUTES.into_iter().collect();
+        let items = collection.search_items(attributes.clone()).map_err(|e| e.to_string())?;
+
+        match items.first() {
+            Some(item) => Self::from_bytes(&item.get_secret().map_err(|e| e.to_string())?),
+            None       => {
+                let key = Self::generate();
+                collection.create_item(KEY_LABEL, attributes, &key.0, false, "application/octet-stream")
+                    .map_err(|e| e.to_string())?;
+
+                Ok(key)
+            }
+        }
+    }
+
+    fn from_file(file: &PathBuf) -> Result<SecretKey, String> {
+        if let Ok(bytes) = fs::read(file) {
+            return Self::from_bytes(&bytes);
+        }
+
+        let key = Self::generate();
+
+        create_secrets_dir()?;
+        OpenOptions::new().write(true).create_new(true).mode(0o600).open(file)
+            .and_then(|mut f| f.write_all(&key.0))
+            .map_err(|e| format!("Could not write `{}`: {}", file.to_str().unwrap(), e))?;
+
+        Ok(key)
+    }
+}
+
+///
+/// ## Secrets
+///
+/// An extension's secrets (API tokens...), encrypted (XChaCha20-Poly1305) in
+/// `~/.local/share/avdan-js-secrets/<hash of its identity>`.
+///
+/// The extension's identity (see [`crate::Avdan::loader::Extension::identity`]) picks the file
+/// *and* is authenticated with it, so a file copied to another extension's name won't decrypt.
+///
+
+pub struct Secrets {
+    identity : String,
+}
+
+impl Secrets {
+    pub fn into_scope<'a>(identity: &str, scope: &mut HandleScope<'a>) -> () {
+        scope.set_slot(Secrets {
+            identity : identity.to_string(),
+        });
+    }
+
+    /// The identity of the extension running in the isolate.
+    pub fn from_scope<'a>(scope: &mut HandleScope<'a>) -> String {
+        scope.get_slot::<Secrets>()
+            .expect("The extension's secrets should be in the isolate!")
+            .identity.clone()
+    }
+
+    fn file(identity: &str) -> Option<PathBuf> {
+        let name = hex::encode(Sha256::digest(identity.as_bytes()));
+
+        secrets_dir().ok().map(|d| d.join(name))
+    }
+
+    fn load(identity: &str, key: &SecretKey) -> Result<BTreeMap<String, String>, String> {
+        let file = Self::file(identity).ok_or("No data directory!")?;
+
+        let bytes = match fs::read(&file) {
+            Ok(b)  => b,
+            Err(_) => return Ok(BTreeMap::new()),
+        };
+
+        if bytes.len() < NONCE_LEN {
+            return Err("The extension's secrets are corrupt!".to_string());
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&key.0));
+
+        let plain = cipher.decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: identity.as_bytes() })
+            .map_err(|_| "Could not decrypt the extension's secrets (was the key changed?)".to_string())?;
+
+        serde_json::from_slice(&plain).map_err(|e| e.to_string())
+    }
+
+    fn save(identity: &str, key: &SecretKey, secrets: &BTreeMap<String, String>) -> Result<(), String> {
+        let file = Self::file(identity).ok_or("No data directory!")?;
+
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&key.0));
+        let plain  = serde_json::to_vec(secrets).unwrap();
+
+        let ciphertext = cipher.encrypt(XNonce::from_slice(&nonce), Payload { msg: &plain, aad: identity.as_bytes() })
+            .map_err(|_| "Could not encrypt the extension's secrets!".to_string())?;
+
+        create_secrets_dir()?;
+
+        // Written next to it (under a name of its own), then moved over it -- never half-written.
+        let mut suffix = [0u8; 8];
+        rand::thread_rng().fill_bytes(&mut suffix);
+
+        let tmp = file.with_extension(format!("{}.tmp", hex::encode(suffix)));
+
+        let result = OpenOptions::new().write(true).create_new(true).mode(0o600).open(&tmp)
+            .and_then(|mut f| f.write_all(&nonce).and(f.write_all(&ciphertext)))
+            .and_then(|_| fs::rename(&tmp, &file));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+
+        result.map_err(|e| e.to_string())
+    }
+
+    /// Does something with an extension's secrets, returning the secret got (or deleted).
+    /// (Blocks -- the Secret Service may ask the user to unlock it.)
+    pub fn run(identity: &str, action: SecretAction) -> Result<Option<String>, String> {
+        // A sandboxed extension can't reach its secrets, its parent does.
+        if Sandbox::is_child() {
+            return match Sandbox::request(&SandboxRequest::Secret { action })? {
+                SandboxResponse::Secret { value } => Ok(value),
+                _                                 => Err("Unexpected response from the sandbox's parent!".to_string()),
+            };
+        }
+
+        // Read, changed and written back as one -- so concurrent changes aren't lost.
+        let _lock = SecretsLock::acquire()?;
+
+        let key = SecretKey::load()?;
+        let mut secrets = Self::load(identity, &key)?;
+
+        match action {
+            SecretAction::Get { key: name } => Ok(secrets.get(&name).cloned()),
+
+            SecretAction::Set { key: name, value } => {
+                secrets.insert(name, value);
+                Self::save(identity, &key, &secrets).map(|_| None)
+            },
+
+            SecretAction::Delete { key: name } => {
+                let old = secrets.remove(&name);
+
+                if old.is_some() {
+                    Self::save(identity, &key, &secrets)?;
+                }
+
+                Ok(old)
+            },
+        }
+    }
+}
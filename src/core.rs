@@ -1,3 +1,5 @@
 mod js;
+mod types;
 
-pub use js::*;
\ No newline at end of file
+pub use js::*;
+pub use types::*;
@@ -1,7 +1,14 @@
 use v8::{HandleScope, Local, Object, Value,};
 
+use super::Declaration;
+
 pub trait JSApi {
     fn js<'a>(&self, scope: &mut HandleScope<'a>) -> Local<'a, Object>;
+
+    /// Describes the API for the generated TypeScript declarations.
+    fn declaration(&self) -> Declaration {
+        Declaration::new()
+    }
 }
 
 pub trait AvJSObject {
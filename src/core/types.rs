@@ -0,0 +1,49 @@
+///
+/// ## Signature
+///
+/// The TypeScript signature of a JS function, made by `#[signature(...)]`.
+///
+
+#[derive(Clone, Copy)]
+pub struct Signature {
+    pub name       : &'static str,
+    pub params     : &'static str,
+    pub returns    : &'static str,
+    pub permission : Option<&'static str>,
+}
+
+///
+/// ## Declaration
+///
+/// What a [`super::JSApi`] looks like from TypeScript:
+/// its functions, and any types they mention.
+///
+
+#[derive(Clone, Default)]
+pub struct Declaration {
+    pub description : Option<&'static str>,
+    pub functions   : Vec<Signature>,
+    pub types       : Vec<&'static str>,
+}
+
+impl Declaration {
+    pub fn new() -> Declaration {
+        Declaration::default()
+    }
+
+    pub fn describe(mut self, description: &'static str) -> Declaration {
+        self.description = Some(description);
+        self
+    }
+
+    pub fn function(mut self, signature: Signature) -> Declaration {
+        self.functions.push(signature);
+        self
+    }
+
+    /// Extra TypeScript (interfaces, type aliases...), emitted as-is into the API's module.
+    pub fn types(mut self, ts: &'static str) -> Declaration {
+        self.types.push(ts);
+        self
+    }
+}
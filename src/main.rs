@@ -1,13 +1,246 @@
 use std::env;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
 
+use colored::Colorize;
 use Avdan::Runtime;
+use Avdan::api::AvdanAPI;
+use Avdan::loader::{Extension, ExtensionSignature, Keyring, Package, PACKAGE_EXTENSION};
+use Avdan::security::{AuditLog, AuditKind, Grants, Policy, format_timestamp, registry};
 
 mod Avdan;
 mod core;
 
+fn pack(args: &[String]) -> () {
+    let dir = args.get(0).expect("Extension path not specified!");
+    let out = match args.get(1) {
+        Some(out) => out.clone(),
+        None      => {
+            let name = Path::new(dir).canonicalize().unwrap();
+            format!("{}.{}", name.file_name().unwrap().to_str().unwrap(), PACKAGE_EXTENSION)
+        }
+    };
+
+    match Package::pack(dir, &out) {
+        Ok(_)    => println!("{} {}", "Packed".green(), out),
+        Err(err) => println!("{}\n{}", "Failed to pack extension!".bright_red(), err),
+    }
+}
+
+fn keygen(args: &[String]) -> () {
+    let out = args.get(0).expect("Secret key file not specified!");
+    let (secret, public) = ExtensionSignature::keygen();
+
+    // Only readable by its owner -- and never over an existing key.
+    let written = OpenOptions::new().write(true).create_new(true).mode(0o600).open(out)
+        .and_then(|mut f| f.write_all(secret.as_bytes()));
+
+    match written {
+        Ok(_)    => println!("{} {}\nPublic key: {}", "Wrote secret key to".green(), out, public.yellow()),
+        Err(err) => println!("{}\n{}", "Failed to write secret key!".bright_red(), err),
+    }
+}
+
+fn sign(args: &[String]) -> () {
+    let (dir, key, publisher) = match (args.get(0), args.get(1), args.get(2)) {
+        (Some(d), Some(k), Some(p)) => (d, k, p),
+        _ => return println!("Usage: proj sign <extension folder> <secret key file> <publisher>"),
+    };
+
+    let result = fs::read_to_string(key)
+        .map_err(|e| e.to_string())
+        .and_then(|secret| ExtensionSignature::sign(Path::new(dir), publisher, &secret));
+
+    match result {
+        Ok(_)    => println!("{} {} as {}", "Signed".green(), dir, publisher.bold()),
+        Err(err) => println!("{}\n{}", "Failed to sign extension!".bright_red(), err),
+    }
+}
+
+fn trust(args: &[String]) -> () {
+    let (publisher, key) = match (args.get(0), args.get(1)) {
+        (Some(p), Some(k)) => (p, k),
+        _ => return println!("Usage: proj trust <publisher> <public key>"),
+    };
+
+    let mut keyring = Keyring::load();
+
+    match keyring.trust(publisher, key).and_then(|_| keyring.save()) {
+        Ok(_)    => println!("{} {}", "Trusted".green(), publisher.bold()),
+        Err(err) => println!("{}\n{}", "Failed to trust publisher!".bright_red(), err),
+    }
+}
+
+fn types(args: &[String]) -> () {
+    let ts = AvdanAPI::typescript();
+
+    match args.get(0) {
+        None      => print!("{}", ts),
+        Some(out) => match fs::write(out, ts) {
+            Ok(_)    => println!("{} {}", "Wrote".green(), out),
+            Err(err) => println!("{}\n{}", "Failed to write declarations!".bright_red(), err),
+        }
+    }
+}
+
+fn grants(args: &[String]) -> () {
+    let dir = args.get(0).expect("Extension path not specified!");
+    let extension = Extension::from_manifest(dir);
+    let mut grants = Grants::load(&extension.identity());
+
+    match (args.get(1).map(|a| a.as_str()), args.get(2)) {
+        (Some("revoke"), Some(perm)) => {
+            match grants.revoke(perm) {
+                true  => match grants.save() {
+                    Ok(_)    => println!("{} '{}'", "Revoked".green(), perm),
+                    Err(err) => println!("{}\n{}", "Failed to save grants!".bright_red(), err),
+                },
+                false => println!("'{}' was not granted.", perm),
+            }
+        },
+
+        (None, _) => {
+            if grants.list().is_empty() {
+                println!("{} has no runtime grants.", extension.name());
+            }
+
+            for g in grants.list() {
+                let expires = g.expires.map(|e| format!("expires {}", e)).unwrap_or("never expires".to_string());
+                println!("  {} ({})", g.permission.yellow(), expires);
+            }
+        },
+
+        _ => println!("Usage: proj grants <extension> [revoke <permission>]"),
+    }
+}
+
+fn permissions() -> () {
+    let registry = registry();
+    let width = registry.iter().map(|p| p.name.len()).max().unwrap_or(0);
+
+    for p in registry {
+        println!("  {}  {}", format!("{:width$}", p.name, width = width).yellow(), p.description);
+    }
+}
+
+fn policy(args: &[String]) -> () {
+    let policy = match Policy::load() {
+        Ok(p)    => p,
+        Err(err) => return println!("{}\n{}", "Failed to load policy!".bright_red(), err),
+    };
+
+    if policy.files.is_empty() {
+        println!("No policy files.");
+    }
+
+    let extension = args.get(0).map(|dir| Extension::from_manifest(dir));
+
+    for (path, file) in policy.files.iter() {
+        let rules = match &extension {
+            Some(e) => file.rules_for(&e.identity()),
+            None    => vec![&file.rules],
+        };
+
+        println!("{}\n{}", path.to_str().unwrap().bold(), serde_json::to_string_pretty(&rules).unwrap());
+    }
+
+    if let Some(e) = extension {
+        println!("\n{} {} ({})", "Effective constraints of".green(), e.name().bold(), e.identity());
+
+        for p in e.security().permissions() {
+            println!("  {}", p.yellow());
+        }
+
+        for c in e.security().commands() {
+            println!("  $ {}", c.name.cyan());
+        }
+
+        for removed in e.restricted() {
+            println!("  {} {}", "removed".bright_red(), removed);
+        }
+    }
+}
+
+fn audit(args: &[String]) -> () {
+    let flag  = |f: &str| args.iter().any(|a| a == f);
+    let value = |f: &str| args.iter().find_map(|a| a.strip_prefix(f));
+
+    let extension  = args.iter().find(|a| !a.starts_with("--"));
+    let permission = value("--permission=");
+
+    let entries = AuditLog::read().into_iter()
+        .filter(|e| extension.map(|x| &e.extension == x || e.identity.as_ref() == Some(x)).unwrap_or(true))
+        .filter(|e| permission.map(|p| e.permission.starts_with(p)).unwrap_or(true))
+        .filter(|e| !flag("--denied") || !e.allowed);
+
+    for e in entries {
+        if flag("--json") {
+            println!("{}", serde_json::to_string(&e).unwrap());
+            continue;
+        }
+
+        let decision = match e.allowed {
+            true  => "allow".green(),
+            false => "deny ".bright_red(),
+        };
+
+        let subject = match e.kind {
+            AuditKind::Permission     => e.permission.yellow(),
+            AuditKind::Command        => format!("$ {}", e.permission).cyan(),
+            AuditKind::CodeGeneration => e.permission.magenta(),
+            AuditKind::Quota          => format!("{} (quota)", e.permission).yellow(),
+            AuditKind::Override       => format!("{} (override)", e.permission).bright_yellow(),
+        };
+
+        println!(
+            "{} {} {} {}{}{}",
+            format_timestamp(e.timestamp).dimmed(),
+            decision,
+            e.identity.as_ref().unwrap_or(&e.extension).bold(),
+            subject,
+            e.resource.map(|r| format!(" {}", r)).unwrap_or_default(),
+            e.location.map(|l| format!("  ({})", l).dimmed().to_string()).unwrap_or_default(),
+        );
+    }
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    let mut r = Runtime::new();
-    r.run_extension(args).join();
+    match args.get(1).map(|a| a.as_str()) {
+        // proj pack <extension folder> [out]
+        Some("pack") => pack(&args[2..]),
+
+        // proj keygen <secret key file>
+        Some("keygen") => keygen(&args[2..]),
+
+        // proj sign <extension folder> <secret key file> <publisher>
+        Some("sign") => sign(&args[2..]),
+
+        // proj trust <publisher> <public key>
+        Some("trust") => trust(&args[2..]),
+
+        // proj grants <extension folder> [revoke <permission>]
+        Some("grants") => grants(&args[2..]),
+
+        // proj audit [extension name] [--denied] [--permission=<prefix>] [--json]
+        Some("audit") => audit(&args[2..]),
+
+        // proj policy [extension folder]
+        Some("policy") => policy(&args[2..]),
+
+        // proj permissions
+        Some("permissions") => permissions(),
+
+        // proj types [out.d.ts]
+        Some("types") => types(&args[2..]),
+
+        _ => {
+            let mut r = Runtime::new();
+            r.run_extension(args).join();
+        }
+    }
 }
@@ -0,0 +1,8 @@
+import Debug from "@avdan/debug";
+import row from "./row.html" assert { type: "text" };
+import query from "./query.sql" assert { type: "text" };
+import icon from "./icon.svg" assert { type: "bytes" };
+
+Debug.log(row.replace("{{name}}", "AvdanOS"));
+Debug.log(query.trim());
+Debug.log(`icon.svg is ${icon.byteLength} bytes`, icon instanceof Uint8Array);
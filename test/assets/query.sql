@@ -0,0 +1 @@
+SELECT name, path FROM applications WHERE name LIKE ?;
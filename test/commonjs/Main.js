@@ -0,0 +1,6 @@
+import Debug from "@avdan/debug";
+import strings from "./strings.cjs";
+import legacy from "./legacy/index.js";
+
+Debug.log(strings.shout("Hello from CommonJS"));
+Debug.log(legacy.describe());
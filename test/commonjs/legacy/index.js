@@ -0,0 +1,9 @@
+const { shout } = require("../strings.cjs");
+const Debug = require("@avdan/debug");
+
+module.exports = {
+    describe() {
+        Debug.log("Loaded from", __filename);
+        return shout("module.exports works");
+    }
+};
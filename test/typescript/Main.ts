@@ -0,0 +1,6 @@
+import Debug from "@avdan/debug";
+import { greet, Greeting } from "./greeting.ts";
+
+const greeting : Greeting = { name: "AvdanOS", times: 2 };
+
+Debug.log(greet(greeting));
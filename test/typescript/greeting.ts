@@ -0,0 +1,8 @@
+export interface Greeting {
+    name  : string;
+    times : number;
+}
+
+export function greet({ name, times } : Greeting) : string {
+    return `Hello, ${name}! `.repeat(times).trim();
+}
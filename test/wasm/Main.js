@@ -0,0 +1,14 @@
+import Debug from "@avdan/debug";
+import { add } from "./add.wasm";
+
+Debug.log("add(2, 3) =", add(2, 3));
+
+// Same module, compiled from JS.
+const bytes = new Uint8Array([
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x02, 0x7f, 0x7f, 0x01,
+    0x7f, 0x03, 0x02, 0x01, 0x00, 0x07, 0x07, 0x01, 0x03, 0x61, 0x64, 0x64, 0x00, 0x00, 0x0a, 0x09,
+    0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b
+]);
+
+const { instance } = await WebAssembly.instantiate(bytes);
+Debug.log("instance.exports.add(40, 2) =", instance.exports.add(40, 2));